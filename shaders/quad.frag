@@ -0,0 +1,19 @@
+#version 450
+
+layout(location = 0) in vec2 v_TexCoord;
+layout(location = 1) flat in uint v_page;
+layout(location = 2) in vec4 v_colour;
+
+layout(location = 0) out vec4 o_Target;
+
+layout(set = 1, binding = 0) uniform texture2D t_Color[128];
+layout(set = 1, binding = 1) uniform sampler s_Color;
+
+void main() {
+    vec4 texel = texture(sampler2D(t_Color[v_page], s_Color), v_TexCoord);
+    vec4 colour = texel * v_colour;
+    if(colour.a < 0.01) {
+        discard;
+    }
+    o_Target = colour;
+}
@@ -0,0 +1,46 @@
+#version 450
+
+// INPUTS:
+// The quad corner relative to the anchor in metres, or its position in ndc for screen space quads
+layout(location=0) in vec4 offset;
+// The uv texture coordinate input
+layout(location=1) in vec2 uv_in;
+// The world position the quad hangs from, w is 0.0 for screen space quads
+layout(location=2) in vec4 anchor;
+layout(location=3) in vec4 colour_in;
+// The font page or sprite frame to sample
+layout(location=4) in uint page_in;
+
+// OUTPUTS TO FRAGMENT SHADER
+layout(location=0) out vec2 uv_out;
+layout(location=1) flat out uint page_out;
+layout(location=2) out vec4 colour_out;
+
+layout(set = 0, binding = 0) uniform Uniforms {
+    mat4 ortho;
+    mat4 persp;
+};
+
+void main() {
+    uv_out = uv_in;
+    page_out = page_in;
+    colour_out = colour_in;
+
+    if (anchor.w == 0.0) {
+        // Screen space quads sit in front of everything, drawn in order
+        gl_Position = vec4(offset.xy, 0.0, 1.0);
+        return;
+    }
+
+    // The same shift as sprites, with the anchor standing in for the sprite centre so a whole
+    // block of text moves together
+    vec4 centre = vec4(anchor.xyz, 1.0);
+    vec4 p_c = persp * centre;
+    vec4 o_c = ortho * centre;
+    vec4 o_pos = ortho * vec4(anchor.xyz + offset.xyz, 1.0);
+
+    vec4 d_ndc = p_c/p_c.w - o_c/o_c.w;
+    vec4 pos_ndc = o_pos/o_pos.w + d_ndc;
+
+    gl_Position = pos_ndc * o_pos.w;
+}
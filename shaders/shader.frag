@@ -2,16 +2,82 @@
 
 layout(location = 0) in vec2 v_TexCoord;
 layout(location = 1) flat in uint v_tex_id;
+layout(location = 2) flat in vec2 v_uv_offset;
+layout(location = 3) flat in vec4 v_fog;
+layout(location = 4) flat in vec4 v_grade;
+layout(location = 5) in vec3 v_world;
+layout(location = 6) flat in vec3 v_axis_x;
+layout(location = 7) flat in vec3 v_axis_y;
 
 layout(location = 0) out vec4 o_Target;
 
+layout(set = 0, binding = 0) uniform Uniforms {
+    mat4 ortho;
+    mat4 persp;
+    vec4 eye;
+    vec4 look;
+    vec4 fog_colour;
+    vec4 fog_params;
+    vec4 band_ranges[4];
+    vec4 band_grades[4];
+    uvec4 band_count;
+    vec4 ambient;
+    uvec4 light_count;
+    vec4 light_positions[8];
+    vec4 light_colours[8];
+    vec4 light_spots[8];
+    vec4 light_params[8];
+};
+
 layout(set = 1, binding = 0) uniform texture2D t_Color[128];
 layout(set = 1, binding = 1) uniform sampler s_Color;
 
+layout(set = 2, binding = 0) uniform texture2D t_Normal[128];
+layout(set = 2, binding = 1) uniform texture2D t_Emissive[128];
+layout(set = 2, binding = 2) uniform sampler s_Maps;
+
+// The light falling on a surface facing `normal`, from every light whose depth range reaches it
+vec3 lights(vec3 normal) {
+    vec3 total = vec3(0.0);
+    for (uint i = 0u; i < light_count.x; i++) {
+        vec3 to_light = light_positions[i].xyz - v_world;
+        float reach = 1.0 - abs(to_light.z) / max(light_params[i].y, 0.0001);
+        float across = length(to_light.xy) / max(light_positions[i].w, 0.0001);
+        float falloff = clamp(1.0 - across * across, 0.0, 1.0);
+        falloff *= falloff * clamp(reach, 0.0, 1.0);
+
+        vec2 spot = light_spots[i].xy;
+        if (spot != vec2(0.0) && length(to_light.xy) > 0.0) {
+            float cosine = dot(normalize(-to_light.xy), spot);
+            float edge = max(light_spots[i].w - light_spots[i].z, 0.0001);
+            falloff *= clamp((cosine - light_spots[i].z) / edge, 0.0, 1.0);
+        }
+
+        // The light hangs in front of the sprite, towards the camera
+        vec3 direction = normalize(vec3(to_light.xy, -light_params[i].x));
+        total += light_colours[i].rgb * max(dot(normal, direction), 0.0) * falloff;
+    }
+    return total;
+}
+
 void main() {
-    vec4 texel = texture(sampler2D(t_Color[v_tex_id], s_Color), v_TexCoord);
+    // Only wrap scrolling sprites, so the edges of still ones sample exactly as before.
+    vec2 uv = v_uv_offset == vec2(0.0) ? v_TexCoord : fract(v_TexCoord + v_uv_offset);
+    vec4 texel = texture(sampler2D(t_Color[v_tex_id], s_Color), uv);
     if(texel.a < 0.5) {
         discard;
     }
-    o_Target = texel;
+    vec3 colour = texel.rgb;
+    float grey = dot(colour, vec3(0.2126, 0.7152, 0.0722));
+    colour = mix(colour, vec3(grey), v_grade.a) * v_grade.rgb;
+
+    // Normal maps have green up and blue out of the sprite, towards the camera
+    vec3 n = texture(sampler2D(t_Normal[v_tex_id], s_Maps), uv).xyz * 2.0 - 1.0;
+    vec3 axis_z = cross(v_axis_x, v_axis_y);
+    vec3 normal = normalize(n.x * v_axis_x + n.y * v_axis_y - n.z * axis_z);
+    vec3 emissive = texture(sampler2D(t_Emissive[v_tex_id], s_Maps), uv).rgb;
+    colour = colour * (ambient.rgb + lights(normal)) + emissive;
+
+    colour = mix(colour, v_fog.rgb, v_fog.a);
+    o_Target = vec4(colour, texel.a);
 }
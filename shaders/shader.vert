@@ -12,19 +12,67 @@ layout(location=4) in vec4 model_matrix_2;
 layout(location=5) in vec4 model_matrix_3;
 // The frame id of the sprite it in a texture array (or atlas)
 layout(location=6) in uint tex_id_in;
+// How far the texture has scrolled across the quad, in uv units
+layout(location=7) in vec2 uv_offset_in;
 
 // OUTPUTS TO FRAGMENT SHADER
 // The uv texture coordinate
 layout(location=0) out vec2 uv_out;
 // The frame id of the sprite it in a texture array (used for animated sprites)
 layout(location=1) flat out uint tex_id_out;
+// The uv scroll offset, applied per fragment so the texture wraps
+layout(location=2) flat out vec2 uv_offset_out;
+// The fog colour and how much of it covers the sprite
+layout(location=3) flat out vec4 fog_out;
+// The tint and desaturation of the sprite's colour band
+layout(location=4) flat out vec4 grade_out;
+// The world position of the vertex, for lighting
+layout(location=5) out vec3 world_out;
+// The world directions of the sprite's x and y axes, to turn its normal map
+layout(location=6) flat out vec3 axis_x_out;
+layout(location=7) flat out vec3 axis_y_out;
 
 // We pass both orthographic and perspective projections to the Unifo
 layout(set = 0, binding = 0) uniform Uniforms {
     mat4 ortho;
     mat4 persp;
+    // The camera's atmosphere, see `CameraUniform`
+    vec4 eye;
+    vec4 look;
+    vec4 fog_colour;
+    vec4 fog_params;
+    vec4 band_ranges[4];
+    vec4 band_grades[4];
+    uvec4 band_count;
+    // The time of day's light, see `TimeOfDay`
+    vec4 ambient;
+    // The lights, see `CameraUniform`
+    uvec4 light_count;
+    vec4 light_positions[8];
+    vec4 light_colours[8];
+    vec4 light_spots[8];
+    vec4 light_params[8];
 };
 
+// The fog and colour grading of a sprite whose centre is `depth` from the eye
+void atmosphere(float depth) {
+    float fog = 0.0;
+    if (fog_params.w == 1.0) {
+        fog = clamp((depth - fog_params.x) / max(fog_params.y - fog_params.x, 0.0001), 0.0, 1.0);
+    } else if (fog_params.w == 2.0) {
+        fog = 1.0 - exp(-fog_params.z * max(depth - fog_params.x, 0.0));
+    }
+    fog_out = vec4(fog_colour.rgb, fog * fog_colour.a);
+
+    grade_out = vec4(1.0, 1.0, 1.0, 0.0);
+    for (uint i = 0u; i < band_count.x; i++) {
+        if (depth >= band_ranges[i].x && depth < band_ranges[i].y) {
+            grade_out = band_grades[i];
+            break;
+        }
+    }
+}
+
 void main() {
 
     mat4 model = mat4(
@@ -36,7 +84,8 @@ void main() {
 
     // Pass the uv texture coordinate and animation frame id through unchanged
     uv_out = uv_in;
-    tex_id_out = tex_id_out;
+    tex_id_out = tex_id_in;
+    uv_offset_out = uv_offset_in;
 
     // 1. We assume our sprites centres are always at (0.0, 0.0, 0.0)
     vec4 centre = vec4(vec3(0.0), 1.0);
@@ -66,4 +115,13 @@ void main() {
     // 8. Convert back to clip space for output to the rasteriser
     gl_Position = pos_ndc * o_pos.w;
 
+    // The whole sprite gets the atmosphere at its centre, like its parallax
+    vec3 world_centre = (model * centre).xyz;
+    atmosphere(dot(world_centre - eye.xyz, look.xyz));
+
+    // Lights shine on the sprite where it is in the world, not where parallax draws it
+    world_out = (model * vertex).xyz;
+    axis_x_out = normalize(model_matrix_0.xyz);
+    axis_y_out = normalize(model_matrix_1.xyz);
+
 }
\ No newline at end of file
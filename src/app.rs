@@ -90,7 +90,7 @@ impl App {
         mut game: Game<'static>,
         sprites: Vec<SpriteAsset>,
     ) {
-        let sc_desc = wgpu::SwapChainDescriptor {
+        let mut sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
             width: self.size.width,
             height: self.size.height,
@@ -100,6 +100,8 @@ impl App {
         };
         let mut swap_chain = self.device.create_swap_chain(&self.surface, &sc_desc);
 
+        game.register_sprite_assets(&sprites);
+        game.screen_size = (self.size.width, self.size.height);
         let mut renderer = Renderer::init(&sc_desc, &mut self.device, &self.queue, sprites);
 
         log::info!("Entering render loop...");
@@ -123,6 +125,22 @@ impl App {
                     | WindowEvent::CloseRequested => {
                         *control_flow = ControlFlow::Exit;
                     }
+                    WindowEvent::Resized(size)
+                    | WindowEvent::ScaleFactorChanged {
+                        new_inner_size: &mut size,
+                        ..
+                    } => {
+                        // Minimised windows report a zero size, which can't back a swap chain.
+                        if size.width == 0 || size.height == 0 {
+                            return;
+                        }
+                        self.size = size;
+                        sc_desc.width = size.width;
+                        sc_desc.height = size.height;
+                        swap_chain = self.device.create_swap_chain(&self.surface, &sc_desc);
+                        renderer.resize(&self.device, &sc_desc);
+                        game.screen_size = (size.width, size.height);
+                    }
                     _ => game.capture_input(event),
                 },
                 event::Event::RedrawRequested(_) => {
@@ -1,8 +1,89 @@
-use std::path::PathBuf;
+use serde::Deserialize;
+use std::{error::Error, fmt, io, path::PathBuf};
 
+/// Failure to load a data file such as a camera timeline, or an image.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "could not read file: {}", err),
+            LoadError::Parse(msg) => write!(f, "could not parse file: {}", msg),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for LoadError {
+    fn from(err: image::ImageError) -> Self {
+        match err {
+            image::ImageError::IoError(err) => LoadError::Io(err),
+            err => LoadError::Parse(err.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct SpriteAsset {
     pub id: String,
+    #[serde(default)]
     pub frames: Vec<PathBuf>,
+    /// Size in pixels of a texture cameras can render into, for sprites that show a camera's
+    /// view instead of image frames.
+    #[serde(default)]
+    pub render_target: Option<(u32, u32)>,
+    /// For tilemaps, the grid of tiles drawn from the tileset in the first frame. The whole
+    /// grid is one sprite, centred on its entity's position.
+    #[serde(skip)]
+    pub tiles: Option<TileGrid>,
+    /// Borders that keep their size when the sprite is drawn stretched, by a
+    /// [`crate::SpriteSize`] or a [`crate::ui::UiNode`].
+    #[serde(default)]
+    pub nine_slice: Option<Insets>,
+    /// A normal map for each frame, with green up, for [`crate::light::Light`]s to light its
+    /// bumps. Left empty the sprite is flat.
+    #[serde(default)]
+    pub normal_maps: Vec<PathBuf>,
+    /// An image for each frame added on top of the lit sprite, for parts that glow in the dark.
+    /// Left empty nothing glows.
+    #[serde(default)]
+    pub emissive_maps: Vec<PathBuf>,
+}
+
+/// A grid of tiles cut from a tileset image.
+#[derive(Clone, Debug)]
+pub struct TileGrid {
+    /// Number of tiles across and down the grid.
+    pub width: u32,
+    pub height: u32,
+    /// Size of a tile in pixels.
+    pub tile_size: (u32, u32),
+    /// Pixels around the edge of the tileset image and between its tiles.
+    pub margin: u32,
+    pub spacing: u32,
+    /// Tiles per row of the tileset image.
+    pub columns: u32,
+    /// Row by row from the top left, `None` where there is no tile.
+    pub tiles: Vec<Option<Tile>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Tile {
+    /// Index into the tileset, row by row from the top left.
+    pub index: u32,
+    pub flip_x: bool,
+    pub flip_y: bool,
 }
 
 impl SpriteAsset {
@@ -10,6 +91,172 @@ impl SpriteAsset {
         SpriteAsset {
             id: id.to_string(),
             frames: frames.iter().map(PathBuf::from).collect(),
+            render_target: None,
+            tiles: None,
+            nine_slice: None,
+            normal_maps: vec![],
+            emissive_maps: vec![],
+        }
+    }
+    /// A tilemap drawing `tiles` from the `tileset` image in a single draw call.
+    pub fn tilemap(id: &str, tileset: PathBuf, tiles: TileGrid) -> Self {
+        SpriteAsset {
+            id: id.to_string(),
+            frames: vec![tileset],
+            render_target: None,
+            tiles: Some(tiles),
+            nine_slice: None,
+            normal_maps: vec![],
+            emissive_maps: vec![],
+        }
+    }
+    /// A sprite showing whatever the cameras targeting `RenderTarget::Texture(id)` render.
+    pub fn render_target(id: &str, width: u32, height: u32) -> Self {
+        SpriteAsset {
+            id: id.to_string(),
+            frames: vec![],
+            render_target: Some((width, height)),
+            tiles: None,
+            nine_slice: None,
+            normal_maps: vec![],
+            emissive_maps: vec![],
+        }
+    }
+    pub fn with_nine_slice(mut self, insets: Insets) -> Self {
+        self.nine_slice = Some(insets);
+        self
+    }
+    pub fn with_normal_maps(mut self, normal_maps: Vec<&str>) -> Self {
+        self.normal_maps = normal_maps.iter().map(PathBuf::from).collect();
+        self
+    }
+    pub fn with_emissive_maps(mut self, emissive_maps: Vec<&str>) -> Self {
+        self.emissive_maps = emissive_maps.iter().map(PathBuf::from).collect();
+        self
+    }
+    /// Size of the sprite in pixels.
+    pub fn size(&self) -> (u32, u32) {
+        self.try_size()
+            .unwrap_or_else(|err| panic!("sprite {:?} can be loaded: {}", self.id, err))
+    }
+    /// Size of the sprite in pixels, or why its first frame can't be read or its maps don't
+    /// line up with its frames.
+    pub fn try_size(&self) -> Result<(u32, u32), LoadError> {
+        self.check_maps()?;
+        if let Some(grid) = &self.tiles {
+            return Ok((
+                grid.width * grid.tile_size.0,
+                grid.height * grid.tile_size.1,
+            ));
+        }
+        match self.render_target {
+            Some(size) => Ok(size),
+            None => {
+                let path = self.frames.first().ok_or_else(|| {
+                    LoadError::Parse(format!("sprite {:?} has no frames", self.id))
+                })?;
+                image::image_dimensions(path).map_err(LoadError::from)
+            }
+        }
+    }
+    fn check_maps(&self) -> Result<(), LoadError> {
+        let maps = [
+            ("normal", &self.normal_maps),
+            ("emissive", &self.emissive_maps),
+        ];
+        for (kind, maps) in maps.iter() {
+            if !maps.is_empty() && maps.len() != self.frames.len() {
+                return Err(LoadError::Parse(format!(
+                    "sprite {:?} has {} {} maps for {} frames",
+                    self.id,
+                    maps.len(),
+                    kind,
+                    self.frames.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pixels in from each edge of an image that a nine-slice keeps at their original size while
+/// the middle stretches.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+pub struct Insets {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+impl Insets {
+    pub fn new(left: u32, right: u32, top: u32, bottom: u32) -> Self {
+        Insets {
+            left,
+            right,
+            top,
+            bottom,
         }
     }
+    pub fn uniform(inset: u32) -> Self {
+        Self::new(inset, inset, inset, inset)
+    }
+}
+
+/// A font for [`crate::text::Text`], loaded with [`crate::Game::load_font`].
+#[derive(Clone, Debug)]
+pub struct FontAsset {
+    pub id: String,
+    pub source: FontSource,
+}
+
+#[derive(Clone, Debug)]
+pub enum FontSource {
+    /// A BMFont `.fnt` file in the text format, its pages next to it.
+    BMFont(PathBuf),
+    /// A TrueType font rasterised at `size` pixels high.
+    Ttf { path: PathBuf, size: f32 },
+}
+
+impl FontAsset {
+    pub fn bmfont(id: &str, path: &str) -> Self {
+        FontAsset {
+            id: id.to_string(),
+            source: FontSource::BMFont(PathBuf::from(path)),
+        }
+    }
+    pub fn ttf(id: &str, path: &str, size: f32) -> Self {
+        FontAsset {
+            id: id.to_string(),
+            source: FontSource::Ttf {
+                path: PathBuf::from(path),
+                size,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_message(asset: &SpriteAsset) -> String {
+        match asset.try_size() {
+            Err(LoadError::Parse(msg)) => msg,
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn maps_must_match_the_frames() {
+        let frames = || SpriteAsset::new("lamp", vec!["lamp_0.png", "lamp_1.png"]);
+        assert_eq!(
+            error_message(&frames().with_normal_maps(vec!["lamp_0_n.png"])),
+            "sprite \"lamp\" has 1 normal maps for 2 frames"
+        );
+        assert_eq!(
+            error_message(&frames().with_emissive_maps(vec!["a.png", "b.png", "c.png"])),
+            "sprite \"lamp\" has 3 emissive maps for 2 frames"
+        );
+    }
 }
@@ -1,11 +1,85 @@
-use crate::{app::WINDOW_SIZE, gpu_primitives::CameraUniform, sprite::PIXELS_PER_METRE};
-use glam::{Mat4, Vec3, Vec4};
+use crate::{
+    app::WINDOW_SIZE, camera::atmosphere::MAX_COLOUR_BANDS, gpu_primitives::CameraUniform,
+    light::MAX_LIGHTS, sprite::PIXELS_PER_METRE, Rect,
+};
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
 use std::f32;
 
+pub mod atmosphere;
+pub mod bounds;
+pub mod dolly;
+pub mod follow;
+pub mod shake;
+pub mod sky;
+pub mod timeline;
+
 pub const SPRITE_SCALING_FACTOR: u8 = 2;
 
+// Closest the perspective near plane may get to the perspective eye when the focal distance
+// pushes the eye past the orthographic near plane.
+const MIN_PERSPECTIVE_NEAR: f32 = 0.01;
+// How far the look direction may stray from the z axis before the shortcuts that assume it
+// looks straight down it stop being trusted.
+const LOOK_ALONG_Z_TOLERANCE: f32 = 1e-3;
+
 pub struct ActiveCamera;
 
+/// Where an [`ActiveCamera`] draws. Cameras without one fill the screen.
+#[derive(Clone, Debug)]
+pub struct Viewport {
+    /// Area of the target to draw into, as fractions of its size from the top left.
+    pub rect: Rect,
+    /// Cameras drawing to the same target are drawn in ascending order, later ones on top.
+    pub order: i32,
+    pub target: RenderTarget,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RenderTarget {
+    Screen,
+    /// The texture of the [`SpriteAsset::render_target`](crate::asset::SpriteAsset) with this
+    /// id.
+    Texture(String),
+}
+
+impl Viewport {
+    pub fn new(rect: Rect, order: i32) -> Self {
+        Viewport {
+            rect,
+            order,
+            target: RenderTarget::Screen,
+        }
+    }
+    pub fn with_target(mut self, target: RenderTarget) -> Self {
+        self.target = target;
+        self
+    }
+    /// Size in pixels of the viewport on a target of `target_size` pixels.
+    pub fn resolution(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        let size = self.rect.size();
+        (
+            (size.x * width as f32).round() as u32,
+            (size.y * height as f32).round() as u32,
+        )
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport::new(Rect::new(Vec2::zero(), Vec2::one()), 0)
+    }
+}
+
+/// How [`ParallaxCamera::zoom`] is applied.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ZoomMode {
+    /// Snap to zoom levels where every texel covers a whole number of screen pixels, keeping
+    /// pixel art crisp.
+    Integer,
+    /// Use the zoom as is, for smooth cinematic zooms.
+    Smooth,
+}
+
 pub trait Camera {
     fn generate_matrix(&self) -> CameraUniform;
 }
@@ -17,43 +91,198 @@ pub struct ParallaxCamera {
     pub fov_y: f32,
     pub near: f32,
     pub far: f32,
+    /// Distance along `look_dir` at which the orthographic and perspective projections agree.
+    /// Sprites at this depth scroll 1:1 with the camera; `fov_y` only changes how strongly the
+    /// layers in front of and behind it move.
+    pub focal_distance: f32,
+    /// Magnification relative to the pixel-perfect default. Both projections are scaled
+    /// together, so layers keep their relative scroll speeds while zooming.
+    pub zoom: f32,
+    pub zoom_mode: ZoomMode,
+    // Size in pixels of the area the camera draws into. Owned by the camera's `Viewport`, which
+    // sets it when the scene is built.
+    pub(crate) resolution: (u32, u32),
+    /// Off-centre shift of both projections in normalised device coordinates, which moves the
+    /// vanishing point away from the middle of the screen. `Vec2::new(0.0, -1.0 / 3.0)` puts
+    /// the horizon on the lower third.
+    pub lens_shift: Vec2,
+    /// The world direction that appears upwards on screen before `roll` is applied.
+    pub up: Vec3,
+    /// Rotation in radians around `look_dir`, applied to both projections so the parallax
+    /// shift follows the tilted screen axes.
+    pub roll: f32,
 }
 
 impl ParallaxCamera {
+    /// The focal plane starts where the field of view puts it, with the perspective camera at
+    /// `eye`; move it with [`ParallaxCamera::with_focal_distance`].
     pub fn new(eye: glam::Vec3, look_to: glam::Vec3, fov_y: f32, near: f32, far: f32) -> Self {
-        ParallaxCamera {
+        let mut camera = ParallaxCamera {
             eye,
             look_dir: look_to,
             fov_y,
             near,
             far,
+            focal_distance: 0.0,
+            zoom: 1.0,
+            zoom_mode: ZoomMode::Integer,
+            resolution: (WINDOW_SIZE.width, WINDOW_SIZE.height),
+            lens_shift: Vec2::zero(),
+            up: Vec3::unit_y(),
+            roll: 0.0,
+        };
+        // With a focal distance of zero the offset is the focal distance that leaves the
+        // perspective camera at `eye`.
+        camera.focal_distance = camera.perspective_offset();
+        camera
+    }
+    pub fn with_focal_distance(mut self, focal_distance: f32) -> Self {
+        self.focal_distance = focal_distance;
+        self
+    }
+
+    /// The zoom actually applied once `zoom_mode` has been taken into account.
+    pub fn effective_zoom(&self) -> f32 {
+        match self.zoom_mode {
+            ZoomMode::Smooth => self.zoom,
+            ZoomMode::Integer => {
+                let pixel_scale = (SPRITE_SCALING_FACTOR as f32 * self.zoom).round().max(1.0);
+                pixel_scale / SPRITE_SCALING_FACTOR as f32
+            }
         }
     }
+
+    /// Size in pixels of the area the camera draws into, set from its [`Viewport`] every frame.
+    pub fn resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+
+    /// Width and height of the orthographic frustum in metres.
+    pub fn ortho_size(&self) -> (f32, f32) {
+        let pixels_per_metre =
+            SPRITE_SCALING_FACTOR as f32 * PIXELS_PER_METRE as f32 * self.effective_zoom();
+        let w = (self.resolution.0 as f32) / pixels_per_metre;
+        let h = (self.resolution.1 as f32) / pixels_per_metre;
+        (w, h)
+    }
+
+    /// The area of the x/y plane inside the orthographic frustum, taking the lens shift into
+    /// account. Assumes the camera looks down the z axis with `up` along y and no roll.
+    pub fn ortho_view_rect(&self) -> Rect {
+        let (w, h) = self.ortho_size();
+        let half_extents = Vec2::new(w / 2.0, h / 2.0);
+        let centre = Vec2::new(self.eye.x, self.eye.y) - self.lens_shift * half_extents;
+        Rect::from_centre(centre, half_extents)
+    }
+
+    /// Whether the camera looks down the z axis, as [`ParallaxCamera::visible_region`] and the
+    /// depth bands of the spatial index assume.
+    pub fn looks_along_z(&self) -> bool {
+        self.look_dir.normalize().z >= 1.0 - LOOK_ALONG_Z_TOLERANCE
+    }
+
+    /// The area of the x/y plane at `depth` along the look direction where the centre of a
+    /// sprite with `half_extents` has to be for any of it to be on screen. Used to find what
+    /// a camera can see without projecting everything; assumes the camera looks down the z
+    /// axis.
+    pub fn visible_region(&self, depth: f32, half_extents: Vec2) -> Rect {
+        let (w, h) = self.ortho_size();
+        let aspect = self.resolution.0 as f32 / self.resolution.1 as f32;
+
+        // A centre's position in normalised device coordinates comes from the perspective
+        // projection, the quad around it has its orthographic size.
+        let distance = (depth + self.perspective_offset()).max(0.0);
+        let world_per_ndc = Vec2::new(aspect, 1.0) * distance * self.tan_half_fov();
+        let ndc_half = Vec2::one() + half_extents / Vec2::new(w / 2.0, h / 2.0);
+        let half = ndc_half * world_per_ndc;
+        let offset = -self.lens_shift * world_per_ndc;
+
+        let (right, up) = self.screen_axes();
+        let centre = self.eye + right * offset.x + up * offset.y;
+        let half_extents = Vec2::new(
+            (right.x * half.x).abs() + (up.x * half.y).abs(),
+            (right.y * half.x).abs() + (up.y * half.y).abs(),
+        );
+        Rect::from_centre(Vec2::new(centre.x, centre.y), half_extents)
+    }
+
+    // Tangent of half the vertical fov of the perspective projection. Zooming narrows it by the
+    // same factor as the orthographic frustum.
+    fn tan_half_fov(&self) -> f32 {
+        (self.fov_y / 2.0).tan() / self.effective_zoom()
+    }
+
+    /// How far behind `eye` the perspective camera sits so that its frustum matches the
+    /// orthographic frustum at `focal_distance`. Negative when it sits in front of `eye`.
+    /// Zoom scales both frustums equally so it does not change this.
+    pub(crate) fn perspective_offset(&self) -> f32 {
+        let (_, h) = self.ortho_size();
+        (h / 2.0) / self.tan_half_fov() - self.focal_distance
+    }
+
+    /// How fast a layer at `depth` scrolls across the screen relative to the focal plane.
+    pub fn scroll_factor(&self, depth: f32) -> f32 {
+        let offset = self.perspective_offset();
+        (self.focal_distance + offset) / (depth + offset)
+    }
+
+    /// The `fov_y` at which a layer at `depth` scrolls at `factor` times the speed of the focal
+    /// plane, for dialling parallax strength in terms of a reference layer. `factor` must be
+    /// between 0 and 1 for layers behind the focal plane, and above 1 for layers in front.
+    pub fn fov_for_scroll_factor(&self, depth: f32, factor: f32) -> f32 {
+        let (_, h) = self.ortho_size();
+        let tan_half_fov =
+            (h / 2.0) * (1.0 - factor) / (factor * (depth - self.focal_distance));
+        2.0 * (tan_half_fov * self.effective_zoom()).atan()
+    }
+
     pub fn generate_ortho(&self) -> glam::Mat4 {
-        let h =
-            (WINDOW_SIZE.height as f32) / (SPRITE_SCALING_FACTOR as f32 * PIXELS_PER_METRE as f32);
-        let w =
-            (WINDOW_SIZE.width as f32) / (SPRITE_SCALING_FACTOR as f32 * PIXELS_PER_METRE as f32);
+        let (w, h) = self.ortho_size();
 
         let mx_ortho =
             glam::Mat4::orthographic_lh(-w / 2.0, w / 2.0, -h / 2.0, h / 2.0, self.near, self.far);
 
-        let mx_view = look_to_lh(self.eye, self.look_dir, Vec3::unit_y());
+        let mx_view = self.view(self.eye);
 
-        mx_ortho * mx_view
+        self.shift() * mx_ortho * mx_view
     }
 
     pub fn generate_perspective(&self) -> glam::Mat4 {
+        // Pull the perspective camera back (or push it forward) along the look direction
+        // instead of touching the fov, so the near and far planes stay where they are in the
+        // world.
+        let offset = self.perspective_offset();
+        let eye = self.eye - self.look_dir.normalize() * offset;
+
         let mx_perspective = glam::Mat4::perspective_lh(
-            self.fov_y,
-            WINDOW_SIZE.width as f32 / WINDOW_SIZE.height as f32,
-            self.near,
-            self.far,
+            2.0 * self.tan_half_fov().atan(),
+            self.resolution.0 as f32 / self.resolution.1 as f32,
+            (self.near + offset).max(MIN_PERSPECTIVE_NEAR),
+            self.far + offset,
         );
 
-        let mx_view = look_to_lh(self.eye, self.look_dir, Vec3::unit_y());
+        let mx_view = self.view(eye);
+
+        self.shift() * mx_perspective * mx_view
+    }
+
+    // Applied in clip space so it moves things by the same amount in normalised device
+    // coordinates whatever their depth.
+    fn shift(&self) -> Mat4 {
+        Mat4::from_translation(self.lens_shift.extend(0.0))
+    }
 
-        mx_perspective * mx_view
+    /// The world directions of the screen's right and up axes, with roll applied.
+    pub fn screen_axes(&self) -> (Vec3, Vec3) {
+        let forward = self.look_dir.normalize();
+        let up = Quat::from_axis_angle(forward, self.roll) * self.up;
+        let right = up.cross(forward).normalize();
+        (right, forward.cross(right))
+    }
+
+    fn view(&self, eye: Vec3) -> Mat4 {
+        let (_, up) = self.screen_axes();
+        look_to_lh(eye, self.look_dir, up)
     }
 }
 
@@ -61,7 +290,24 @@ impl Camera for ParallaxCamera {
     fn generate_matrix(&self) -> CameraUniform {
         let ortho = *self.generate_ortho().as_ref();
         let persp = *self.generate_perspective().as_ref();
-        CameraUniform { ortho, persp }
+        let look = self.look_dir.normalize();
+        CameraUniform {
+            ortho,
+            persp,
+            eye: [self.eye.x, self.eye.y, self.eye.z, 0.0],
+            look: [look.x, look.y, look.z, 0.0],
+            fog_colour: [0.0; 4],
+            fog_params: [0.0; 4],
+            band_ranges: [[0.0; 4]; MAX_COLOUR_BANDS],
+            band_grades: [[0.0; 4]; MAX_COLOUR_BANDS],
+            band_count: [0; 4],
+            ambient: [1.0; 4],
+            light_count: [0; 4],
+            light_positions: [[0.0; 4]; MAX_LIGHTS],
+            light_colours: [[0.0; 4]; MAX_LIGHTS],
+            light_spots: [[0.0; 4]; MAX_LIGHTS],
+            light_params: [[0.0; 4]; MAX_LIGHTS],
+        }
     }
 }
 
@@ -79,3 +325,108 @@ fn look_to_lh(eye: Vec3, dir: Vec3, up: Vec3) -> Mat4 {
         Vec4::new(-s.dot(eye), -u.dot(eye), -f.dot(eye), 1.0),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn camera(eye: Vec3) -> ParallaxCamera {
+        ParallaxCamera::new(eye, Vec3::unit_z(), 1.0, 0.1, 500.0).with_focal_distance(20.0)
+    }
+
+    fn assert_near(a: Vec2, b: Vec2) {
+        assert!((a - b).length() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    // Where `point` ends up in normalised device coordinates under `projection`.
+    fn ndc(projection: Mat4, point: Vec3) -> Vec2 {
+        let clip = projection * point.extend(1.0);
+        Vec2::new(clip.x, clip.y) / clip.w
+    }
+
+    // Where the shader draws `point` of a sprite centred on `centre`: its orthographic
+    // projection, shifted by how far perspective moves the centre.
+    fn parallax(cam: &ParallaxCamera, centre: Vec3, point: Vec3) -> Vec2 {
+        let (ortho, persp) = (cam.generate_ortho(), cam.generate_perspective());
+        ndc(ortho, point) + ndc(persp, centre) - ndc(ortho, centre)
+    }
+
+    // Checks that moving the eye by `step` across the x/y plane scrolls layers at their scroll
+    // factor on both axes, keeping their size on screen.
+    fn assert_layers_scroll_at_their_factor(step: Vec2) {
+        let before = camera(Vec3::zero());
+        let after = camera(step.extend(0.0));
+        let (w, h) = before.ortho_size();
+        // Where the focal plane goes, in normalised device coordinates.
+        let focal = -step * Vec2::new(2.0 / w, 2.0 / h);
+
+        for &depth in [5.0, 20.0, 60.0, 200.0].iter() {
+            let centre = Vec3::new(1.5, -2.0, depth);
+            let corner = centre + Vec3::new(0.5, 0.5, 0.0);
+            let moved = parallax(&after, centre, centre) - parallax(&before, centre, centre);
+            assert_near(moved, focal * before.scroll_factor(depth));
+
+            let size = parallax(&after, centre, corner) - parallax(&after, centre, centre);
+            assert_near(size, Vec2::new(1.0 / w, 1.0 / h));
+        }
+    }
+
+    #[test]
+    fn layers_scroll_at_their_factor_vertically() {
+        assert_layers_scroll_at_their_factor(Vec2::new(0.0, 6.0));
+    }
+
+    #[test]
+    fn layers_scroll_at_their_factor_diagonally() {
+        assert_layers_scroll_at_their_factor(Vec2::new(-4.0, 3.0));
+    }
+
+    #[test]
+    fn ortho_view_rect_follows_vertical_eye() {
+        let cam = camera(Vec3::new(0.0, 7.5, 0.0));
+        let (w, h) = cam.ortho_size();
+        let view = cam.ortho_view_rect();
+        assert_near(view.centre(), Vec2::new(0.0, 7.5));
+        assert_near(view.size(), Vec2::new(w, h));
+    }
+
+    #[test]
+    fn ortho_view_rect_follows_diagonal_eye() {
+        let cam = camera(Vec3::new(-3.0, 4.0, 0.0));
+        let (w, h) = cam.ortho_size();
+        let view = cam.ortho_view_rect();
+        assert_near(view.centre(), Vec2::new(-3.0, 4.0));
+        assert_near(view.size(), Vec2::new(w, h));
+    }
+
+    #[test]
+    fn ortho_view_rect_moves_against_lens_shift() {
+        let mut cam = camera(Vec3::new(2.0, 3.0, 0.0));
+        cam.lens_shift = Vec2::new(0.0, -1.0 / 3.0);
+        let (_, h) = cam.ortho_size();
+        let view = cam.ortho_view_rect();
+        assert_near(view.centre(), Vec2::new(2.0, 3.0 + h / 6.0));
+    }
+
+    #[test]
+    fn lens_shift_moves_projected_centres_by_the_shift() {
+        let centres = [
+            Vec3::new(1.0, 2.0, 20.0),
+            Vec3::new(-4.0, 6.0, 60.0),
+            Vec3::new(3.0, -1.0, 5.0),
+        ];
+        let shift = Vec2::new(0.25, -1.0 / 3.0);
+        let unshifted = camera(Vec3::new(1.0, 3.0, 0.0));
+        let mut shifted = unshifted;
+        shifted.lens_shift = shift;
+
+        for &centre in centres.iter() {
+            let ortho = ndc(shifted.generate_ortho(), centre);
+            assert_near(ortho, ndc(unshifted.generate_ortho(), centre) + shift);
+            let persp = ndc(shifted.generate_perspective(), centre);
+            assert_near(persp, ndc(unshifted.generate_perspective(), centre) + shift);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::gpu_primitives::CameraUniform;
+
+/// The most colour bands a camera can use.
+pub const MAX_COLOUR_BANDS: usize = 4;
+
+/// Aerial perspective for the camera it is attached to: fog and colour grading picked by how
+/// far each sprite's centre is from the eye along the look direction, so distant layers fade
+/// without repainting them. Applies to sprites and repeating layers.
+#[derive(Clone, Debug, Default)]
+pub struct Atmosphere {
+    pub fog: Option<Fog>,
+    /// Checked in order, the first band containing a sprite grades it. Only the first
+    /// [`MAX_COLOUR_BANDS`] are used.
+    pub bands: Vec<ColourBand>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Fog {
+    pub mode: FogMode,
+    /// Linear RGB of the fog, with the most it can cover a sprite in alpha.
+    pub colour: [f32; 4],
+    /// Depth where the fog begins.
+    pub start: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FogMode {
+    /// Thickens evenly until it is at its thickest at this depth.
+    Linear { end: f32 },
+    /// Thickens quickly then levels off; `density` is per metre past the start.
+    Exponential { density: f32 },
+}
+
+/// A tint and desaturation for sprites between two depths.
+#[derive(Clone, Copy, Debug)]
+pub struct ColourBand {
+    pub near: f32,
+    pub far: f32,
+    /// Linear RGB multiplied with the sprite.
+    pub tint: [f32; 3],
+    /// From 0 for the original colours to 1 for greyscale.
+    pub desaturation: f32,
+}
+
+impl Atmosphere {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_fog(mut self, fog: Fog) -> Self {
+        self.fog = Some(fog);
+        self
+    }
+    pub fn with_band(mut self, band: ColourBand) -> Self {
+        self.bands.push(band);
+        self
+    }
+    pub(crate) fn apply(&self, uniform: &mut CameraUniform) {
+        if let Some(fog) = &self.fog {
+            uniform.fog_colour = fog.colour;
+            uniform.fog_params = match fog.mode {
+                FogMode::Linear { end } => [fog.start, end, 0.0, 1.0],
+                FogMode::Exponential { density } => [fog.start, 0.0, density, 2.0],
+            };
+        }
+
+        if self.bands.len() > MAX_COLOUR_BANDS {
+            log::warn!(
+                "only the first {} of {} colour bands are used",
+                MAX_COLOUR_BANDS,
+                self.bands.len()
+            );
+        }
+        let bands = self.bands.iter().take(MAX_COLOUR_BANDS);
+        for (i, band) in bands.enumerate() {
+            let [r, g, b] = band.tint;
+            uniform.band_ranges[i] = [band.near, band.far, 0.0, 0.0];
+            uniform.band_grades[i] = [r, g, b, band.desaturation];
+            uniform.band_count[0] = i as u32 + 1;
+        }
+    }
+}
+
+impl Fog {
+    pub fn linear(colour: [f32; 4], start: f32, end: f32) -> Self {
+        Fog {
+            mode: FogMode::Linear { end },
+            colour,
+            start,
+        }
+    }
+    pub fn exponential(colour: [f32; 4], start: f32, density: f32) -> Self {
+        Fog {
+            mode: FogMode::Exponential { density },
+            colour,
+            start,
+        }
+    }
+}
+
+impl ColourBand {
+    /// A band that leaves colours as they are until given a tint or desaturation.
+    pub fn new(near: f32, far: f32) -> Self {
+        ColourBand {
+            near,
+            far,
+            tint: [1.0, 1.0, 1.0],
+            desaturation: 0.0,
+        }
+    }
+    pub fn with_tint(mut self, tint: [f32; 3]) -> Self {
+        self.tint = tint;
+        self
+    }
+    pub fn with_desaturation(mut self, desaturation: f32) -> Self {
+        self.desaturation = desaturation;
+        self
+    }
+}
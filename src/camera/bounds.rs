@@ -0,0 +1,199 @@
+use crate::{camera::ParallaxCamera, Position, Rect};
+use glam::Vec2;
+use hecs::{Entity, World};
+use std::time::{Duration, Instant};
+
+/// Keeps the orthographic frustum of a [`ParallaxCamera`] inside a level, or inside one of
+/// several rooms. Assumes the camera looks down the z axis.
+pub struct CameraBounds {
+    pub regions: Vec<Rect>,
+    /// Entity whose position picks the active region. The camera eye is used when this is
+    /// `None`, which means the camera can never leave the region it starts in.
+    pub track: Option<Entity>,
+    /// Seconds taken to ease from the limits of one region to the next.
+    pub transition_time: f32,
+    state: Option<BoundsState>,
+}
+
+struct BoundsState {
+    region: usize,
+    from: Vec2,
+    last: Vec2,
+    elapsed: f32,
+}
+
+impl CameraBounds {
+    pub fn new(level: Rect) -> Self {
+        Self::rooms(vec![level])
+    }
+    pub fn rooms(regions: Vec<Rect>) -> Self {
+        CameraBounds {
+            regions,
+            track: None,
+            transition_time: 0.0,
+            state: None,
+        }
+    }
+    pub fn with_track(mut self, entity: Entity) -> Self {
+        self.track = Some(entity);
+        self
+    }
+    pub fn with_transition_time(mut self, seconds: f32) -> Self {
+        self.transition_time = seconds;
+        self
+    }
+    /// The region the camera is currently confined to.
+    pub fn active_region(&self) -> Option<Rect> {
+        self.state.as_ref().map(|state| self.regions[state.region])
+    }
+}
+
+/// Clamps the eye of every camera with [`CameraBounds`]. Add it after any system that moves the
+/// camera.
+pub fn confine_camera(world: &World, dt: Duration, _instant: Instant) {
+    let dt = dt.as_secs_f32();
+    let mut q = world.query::<(&mut ParallaxCamera, &mut CameraBounds)>();
+
+    for (_, (cam, bounds)) in q.iter() {
+        if bounds.regions.is_empty() {
+            continue;
+        }
+
+        let eye = Vec2::new(cam.eye.x, cam.eye.y);
+        let focus = bounds
+            .track
+            .and_then(|entity| world.get::<Position>(entity).ok())
+            .map(|pos| Vec2::new(pos.0.x, pos.0.y))
+            .unwrap_or(eye);
+
+        let current = bounds.state.as_ref().map(|state| state.region);
+        let region = match current {
+            Some(region) if bounds.regions[region].contains(focus) => region,
+            _ => nearest_region(&bounds.regions, focus),
+        };
+
+        // Confine what is on screen, which the lens shift moves away from the eye.
+        let view = cam.ortho_view_rect();
+        let shift = view.centre() - eye;
+        let clamped = confine(&bounds.regions[region], view.centre(), view.size() / 2.0) - shift;
+
+        let transition_time = bounds.transition_time;
+        let state = bounds.state.get_or_insert(BoundsState {
+            region,
+            from: clamped,
+            last: clamped,
+            elapsed: transition_time,
+        });
+        if state.region != region {
+            state.region = region;
+            state.from = state.last;
+            state.elapsed = 0.0;
+        }
+        state.elapsed += dt;
+
+        let confined = if state.elapsed < transition_time {
+            let t = state.elapsed / transition_time;
+            state.from.lerp(clamped, t * t * (3.0 - 2.0 * t))
+        } else {
+            clamped
+        };
+        state.last = confined;
+
+        cam.eye.x = confined.x;
+        cam.eye.y = confined.y;
+    }
+}
+
+fn nearest_region(regions: &[Rect], point: Vec2) -> usize {
+    regions
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| {
+            a.distance_to(point)
+                .partial_cmp(&b.distance_to(point))
+                .expect("region distances are not NaN")
+        })
+        .map(|(i, _)| i)
+        .expect("at least one region")
+}
+
+// Clamps `centre` so a view with `half_extents` stays inside `region`, centring the view on
+// any axis the region is too small to fill.
+fn confine(region: &Rect, centre: Vec2, half_extents: Vec2) -> Vec2 {
+    let axis = |min: f32, max: f32, half: f32, v: f32| {
+        if max - min <= 2.0 * half {
+            (min + max) / 2.0
+        } else {
+            v.max(min + half).min(max - half)
+        }
+    };
+    Vec2::new(
+        axis(region.min.x, region.max.x, half_extents.x, centre.x),
+        axis(region.min.y, region.max.y, half_extents.y, centre.y),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    const EPSILON: f32 = 1e-4;
+
+    // Spawns a camera at `eye` confined to `level` and returns its entity.
+    fn spawn(world: &mut World, eye: Vec3, level: Rect) -> Entity {
+        let cam = ParallaxCamera::new(eye, Vec3::unit_z(), 1.0, 0.1, 500.0);
+        world.spawn((cam, CameraBounds::new(level)))
+    }
+
+    fn eye(world: &World, entity: Entity) -> Vec3 {
+        world
+            .get::<ParallaxCamera>(entity)
+            .expect("camera exists")
+            .eye
+    }
+
+    #[test]
+    fn clamps_both_axes_during_a_vertical_jump() {
+        let level = Rect::new(Vec2::new(-40.0, -5.0), Vec2::new(40.0, 30.0));
+        let mut world = World::new();
+        let entity = spawn(&mut world, Vec3::new(0.0, 5.0, 0.0), level);
+        let (w, h) = world
+            .get::<ParallaxCamera>(entity)
+            .expect("camera exists")
+            .ortho_size();
+        assert!(level.size().x > w && level.size().y > h);
+
+        // Jump up and right past the top right corner of the level.
+        let frame = Duration::from_millis(16);
+        world
+            .get_mut::<ParallaxCamera>(entity)
+            .expect("camera exists")
+            .eye = Vec3::new(100.0, 80.0, 0.0);
+        confine_camera(&world, frame, Instant::now());
+        let top = eye(&world, entity);
+        assert!((top.x - (level.max.x - w / 2.0)).abs() < EPSILON);
+        assert!((top.y - (level.max.y - h / 2.0)).abs() < EPSILON);
+
+        // Fall back down through the bottom of the level.
+        world
+            .get_mut::<ParallaxCamera>(entity)
+            .expect("camera exists")
+            .eye = Vec3::new(0.0, -50.0, 0.0);
+        confine_camera(&world, frame, Instant::now());
+        let bottom = eye(&world, entity);
+        assert!(bottom.x.abs() < EPSILON);
+        assert!((bottom.y - (level.min.y + h / 2.0)).abs() < EPSILON);
+    }
+
+    #[test]
+    fn leaves_the_eye_alone_inside_the_level() {
+        let level = Rect::new(Vec2::new(-40.0, -5.0), Vec2::new(40.0, 30.0));
+        let mut world = World::new();
+        let entity = spawn(&mut world, Vec3::new(3.0, 12.0, 0.0), level);
+        confine_camera(&world, Duration::from_millis(16), Instant::now());
+        let confined = eye(&world, entity);
+        assert!((confined.x - 3.0).abs() < EPSILON);
+        assert!((confined.y - 12.0).abs() < EPSILON);
+    }
+}
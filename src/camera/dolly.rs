@@ -0,0 +1,83 @@
+use crate::{camera::ParallaxCamera, easing::Easing, Position};
+use hecs::{Entity, World};
+use std::time::{Duration, Instant};
+
+/// The depth a [`DollyZoom`] keeps fixed on screen.
+#[derive(Clone, Copy, Debug)]
+pub enum DollyAnchor {
+    /// Distance along the camera's look direction.
+    Depth(f32),
+    /// The depth of an entity's [`Position`], followed as it moves.
+    Entity(Entity),
+}
+
+/// Animates the `fov_y` of a [`ParallaxCamera`] while pinning its focal plane to an anchor.
+/// The anchor stays put on screen while everything in front of and behind it speeds up or
+/// slows down, which gives the vertigo effect when done quickly and a gentle change in parallax
+/// strength when done slowly.
+pub struct DollyZoom {
+    pub anchor: DollyAnchor,
+    pub to_fov: f32,
+    /// Seconds the animation takes.
+    pub duration: f32,
+    pub easing: Easing,
+    from_fov: Option<f32>,
+    elapsed: f32,
+}
+
+impl DollyZoom {
+    pub fn new(anchor: DollyAnchor, to_fov: f32, duration: f32) -> Self {
+        DollyZoom {
+            anchor,
+            to_fov,
+            duration,
+            easing: Easing::EaseInOut,
+            from_fov: None,
+            elapsed: 0.0,
+        }
+    }
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+    /// Starts a new animation from wherever the fov is now.
+    pub fn retarget(&mut self, to_fov: f32, duration: f32) {
+        self.to_fov = to_fov;
+        self.duration = duration;
+        self.from_fov = None;
+        self.elapsed = 0.0;
+    }
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+/// Advances every [`DollyZoom`], keeping the camera's focal plane on the anchor even once the
+/// animation has finished. Finished dollies leave `fov_y` alone, so other systems such as
+/// camera timelines can animate it.
+pub fn dolly_zoom(world: &World, dt: Duration, _instant: Instant) {
+    let mut q = world.query::<(&mut ParallaxCamera, &mut DollyZoom)>();
+
+    for (_, (cam, dolly)) in q.iter() {
+        let depth = match dolly.anchor {
+            DollyAnchor::Depth(depth) => depth,
+            DollyAnchor::Entity(entity) => match world.get::<Position>(entity) {
+                Ok(pos) => (pos.0 - cam.eye).dot(cam.look_dir.normalize()),
+                Err(_) => continue,
+            },
+        };
+        cam.focal_distance = depth;
+        if dolly.is_finished() {
+            continue;
+        }
+
+        let from_fov = *dolly.from_fov.get_or_insert(cam.fov_y);
+        dolly.elapsed = (dolly.elapsed + dt.as_secs_f32()).min(dolly.duration);
+        let t = if dolly.duration > 0.0 {
+            dolly.easing.apply(dolly.elapsed / dolly.duration)
+        } else {
+            1.0
+        };
+        cam.fov_y = from_fov + (dolly.to_fov - from_fov) * t;
+    }
+}
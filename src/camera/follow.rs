@@ -0,0 +1,181 @@
+use crate::{
+    camera::{ParallaxCamera, SPRITE_SCALING_FACTOR},
+    sprite::PIXELS_PER_METRE,
+    Position,
+};
+use glam::Vec2;
+use hecs::{Entity, World};
+use std::time::{Duration, Instant};
+
+/// Moves a [`ParallaxCamera`] in the x/y plane to follow the [`Position`] of a target entity.
+pub struct CameraFollow {
+    pub target: Entity,
+    /// Where the camera sits relative to the target.
+    pub offset: Vec2,
+    /// Half extents of the box around the camera the target can move in without the camera
+    /// following.
+    pub dead_zone: Vec2,
+    /// Seconds of target velocity the camera leads the target by.
+    pub look_ahead: f32,
+    /// Roughly how many seconds the camera takes to catch up, per axis. Zero disables
+    /// smoothing on that axis.
+    pub smooth_time: Vec2,
+    /// Round the camera position to whole screen pixels.
+    pub pixel_snap: bool,
+    state: Option<FollowState>,
+}
+
+struct FollowState {
+    // The smoothed camera position, kept separately from the eye so pixel snapping and later
+    // systems that adjust the eye don't feed back into the smoothing.
+    focus: Vec2,
+    velocity: Vec2,
+    last_target: Vec2,
+}
+
+impl CameraFollow {
+    pub fn new(target: Entity) -> Self {
+        CameraFollow {
+            target,
+            offset: Vec2::zero(),
+            dead_zone: Vec2::zero(),
+            look_ahead: 0.0,
+            smooth_time: Vec2::zero(),
+            pixel_snap: false,
+            state: None,
+        }
+    }
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+    pub fn with_dead_zone(mut self, half_extents: Vec2) -> Self {
+        self.dead_zone = half_extents;
+        self
+    }
+    pub fn with_look_ahead(mut self, seconds: f32) -> Self {
+        self.look_ahead = seconds;
+        self
+    }
+    pub fn with_smoothing(mut self, smooth_time: Vec2) -> Self {
+        self.smooth_time = smooth_time;
+        self
+    }
+    pub fn with_pixel_snap(mut self, pixel_snap: bool) -> Self {
+        self.pixel_snap = pixel_snap;
+        self
+    }
+}
+
+/// Moves every camera with a [`CameraFollow`] towards its target.
+pub fn follow_camera(world: &World, dt: Duration, _instant: Instant) {
+    let dt = dt.as_secs_f32();
+    let mut q = world.query::<(&mut ParallaxCamera, &mut CameraFollow)>();
+
+    for (_, (cam, follow)) in q.iter() {
+        let target = match world.get::<Position>(follow.target) {
+            Ok(pos) => Vec2::new(pos.0.x, pos.0.y),
+            Err(_) => continue,
+        };
+
+        let state = follow.state.get_or_insert(FollowState {
+            focus: Vec2::new(cam.eye.x, cam.eye.y),
+            velocity: Vec2::zero(),
+            last_target: target,
+        });
+
+        let target_velocity = if dt > 0.0 {
+            (target - state.last_target) / dt
+        } else {
+            Vec2::zero()
+        };
+        state.last_target = target;
+
+        let goal = target + follow.offset + target_velocity * follow.look_ahead;
+        let desired = Vec2::new(
+            dead_zone(state.focus.x, goal.x, follow.dead_zone.x),
+            dead_zone(state.focus.y, goal.y, follow.dead_zone.y),
+        );
+
+        state.focus.x = smooth_damp(
+            state.focus.x,
+            desired.x,
+            &mut state.velocity.x,
+            follow.smooth_time.x,
+            dt,
+        );
+        state.focus.y = smooth_damp(
+            state.focus.y,
+            desired.y,
+            &mut state.velocity.y,
+            follow.smooth_time.y,
+            dt,
+        );
+
+        let mut focus = state.focus;
+        if follow.pixel_snap {
+            let pixels_per_metre =
+                SPRITE_SCALING_FACTOR as f32 * PIXELS_PER_METRE as f32 * cam.effective_zoom();
+            focus.x = (focus.x * pixels_per_metre).round() / pixels_per_metre;
+            focus.y = (focus.y * pixels_per_metre).round() / pixels_per_metre;
+        }
+
+        cam.eye.x = focus.x;
+        cam.eye.y = focus.y;
+    }
+}
+
+// Moves `current` just far enough that `goal` is within `half_extent` of it.
+fn dead_zone(current: f32, goal: f32, half_extent: f32) -> f32 {
+    if goal > current + half_extent {
+        goal - half_extent
+    } else if goal < current - half_extent {
+        goal + half_extent
+    } else {
+        current
+    }
+}
+
+// Critically damped spring towards `target`, see Game Programming Gems 4, chapter 1.10.
+fn smooth_damp(current: f32, target: f32, velocity: &mut f32, smooth_time: f32, dt: f32) -> f32 {
+    if smooth_time <= 0.0 {
+        *velocity = 0.0;
+        return target;
+    }
+
+    let omega = 2.0 / smooth_time;
+    let x = omega * dt;
+    let exp = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (*velocity + omega * change) * dt;
+    *velocity = (*velocity - omega * temp) * exp;
+
+    target + (change + temp) * exp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::ZoomMode;
+    use glam::Vec3;
+
+    #[test]
+    fn pixel_snap_lands_on_whole_screen_pixels_when_zoomed() {
+        let mut world = World::new();
+        let target = world.spawn((Position(Vec3::new(0.3712, -1.2345, 30.0)),));
+        let mut cam = ParallaxCamera::new(Vec3::zero(), Vec3::unit_z(), 1.0, 0.1, 500.0);
+        cam.zoom = 1.37;
+        cam.zoom_mode = ZoomMode::Smooth;
+        let entity = world.spawn((cam, CameraFollow::new(target).with_pixel_snap(true)));
+
+        follow_camera(&world, Duration::from_millis(16), Instant::now());
+
+        let cam = world.get::<ParallaxCamera>(entity).expect("camera exists");
+        let pixels_per_metre =
+            SPRITE_SCALING_FACTOR as f32 * PIXELS_PER_METRE as f32 * cam.effective_zoom();
+        for &metres in [cam.eye.x, cam.eye.y].iter() {
+            let pixels = metres * pixels_per_metre;
+            assert!((pixels - pixels.round()).abs() < 1e-3, "{} pixels", pixels);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::camera::ParallaxCamera;
+use glam::Vec2;
+use hecs::World;
+use std::time::{Duration, Instant};
+
+const SEED_X: u32 = 0x68e3_1da4;
+const SEED_Y: u32 = 0xb529_7a4d;
+const SEED_ROLL: u32 = 0x1b56_c4e9;
+
+/// Trauma based screen shake. Only the matrices sent to the GPU are shaken, the eye of the
+/// [`ParallaxCamera`] this is attached to is left untouched.
+pub struct CameraShake {
+    /// Shake intensity between 0 and 1. The camera shakes by the square of this, so small hits
+    /// barely register and big ones stack up.
+    pub trauma: f32,
+    /// Trauma lost per second.
+    pub decay: f32,
+    /// Largest translation in metres along the camera's right and up axes, at full trauma.
+    pub max_offset: Vec2,
+    /// Largest roll in radians, at full trauma.
+    pub max_roll: f32,
+    /// How many times per second the noise driving the shake changes direction.
+    pub frequency: f32,
+    time: f32,
+}
+
+impl CameraShake {
+    pub fn new(max_offset: Vec2, max_roll: f32) -> Self {
+        CameraShake {
+            trauma: 0.0,
+            decay: 1.0,
+            max_offset,
+            max_roll,
+            frequency: 15.0,
+            time: 0.0,
+        }
+    }
+    pub fn with_decay(mut self, decay: f32) -> Self {
+        self.decay = decay;
+        self
+    }
+    pub fn with_frequency(mut self, frequency: f32) -> Self {
+        self.frequency = frequency;
+        self
+    }
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).max(0.0).min(1.0);
+    }
+    /// Translation along the camera's right and up axes and roll for the current frame.
+    pub fn offset(&self) -> (Vec2, f32) {
+        let shake = self.trauma * self.trauma;
+        let t = self.time * self.frequency;
+        let offset = Vec2::new(
+            self.max_offset.x * shake * noise(SEED_X, t),
+            self.max_offset.y * shake * noise(SEED_Y, t),
+        );
+        (offset, self.max_roll * shake * noise(SEED_ROLL, t))
+    }
+    /// A copy of `cam` with the shake for the current frame applied.
+    pub fn apply(&self, cam: &ParallaxCamera) -> ParallaxCamera {
+        let (offset, roll) = self.offset();
+        let (right, up) = cam.screen_axes();
+
+        let mut shaken = *cam;
+        shaken.eye += right * offset.x + up * offset.y;
+        shaken.roll += roll;
+        shaken
+    }
+}
+
+/// Advances and decays every [`CameraShake`].
+pub fn shake_camera(world: &World, dt: Duration, _instant: Instant) {
+    let dt = dt.as_secs_f32();
+    let mut q = world.query::<&mut CameraShake>();
+
+    for (_, shake) in q.iter() {
+        shake.time += dt;
+        shake.trauma = (shake.trauma - shake.decay * dt).max(0.0);
+    }
+}
+
+// Smooth 1D value noise in -1..1.
+fn noise(seed: u32, t: f32) -> f32 {
+    let i = t.floor();
+    let f = t - i;
+    let a = hash(seed, i as i32);
+    let b = hash(seed, i as i32 + 1);
+    a + (b - a) * f * f * (3.0 - 2.0 * f)
+}
+
+fn hash(seed: u32, i: i32) -> f32 {
+    let mut x = (i as u32).wrapping_mul(0x9e37_79b1) ^ seed;
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x2c1b_3c6d);
+    x ^= x >> 12;
+    x = x.wrapping_mul(0x297a_2d39);
+    x ^= x >> 15;
+    (x as f32 / u32::MAX as f32) * 2.0 - 1.0
+}
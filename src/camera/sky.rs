@@ -0,0 +1,61 @@
+/// What the camera it is attached to draws behind every sprite: a clear colour, a vertical
+/// gradient over it, then backdrop images. All of it fills the camera's viewport and is at an
+/// infinite distance, so it never moves with the camera. Change the fields from a system to
+/// animate it.
+#[derive(Clone, Debug)]
+pub struct Sky {
+    /// Linear RGBA.
+    pub clear_colour: [f32; 4],
+    pub gradient: Option<SkyGradient>,
+    /// Drawn in order over the gradient.
+    pub backdrops: Vec<Backdrop>,
+}
+
+/// Linear RGBA at the top and bottom of the viewport, blended in between.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SkyGradient {
+    pub top: [f32; 4],
+    pub bottom: [f32; 4],
+}
+
+/// A frame of a sprite scaled to cover the whole viewport, keeping its aspect ratio and
+/// cropping what doesn't fit.
+#[derive(Clone, Debug)]
+pub struct Backdrop {
+    pub sprite: String,
+    pub frame_id: u32,
+    /// Linear RGBA multiplied with the image, the alpha fades it in and out.
+    pub colour: [f32; 4],
+}
+
+impl Sky {
+    pub fn new(clear_colour: [f32; 4]) -> Self {
+        Sky {
+            clear_colour,
+            gradient: None,
+            backdrops: vec![],
+        }
+    }
+    pub fn with_gradient(mut self, top: [f32; 4], bottom: [f32; 4]) -> Self {
+        self.gradient = Some(SkyGradient { top, bottom });
+        self
+    }
+    pub fn with_backdrop(mut self, backdrop: Backdrop) -> Self {
+        self.backdrops.push(backdrop);
+        self
+    }
+}
+
+impl Backdrop {
+    pub fn new(sprite: &str) -> Self {
+        Backdrop {
+            sprite: sprite.to_string(),
+            frame_id: 0,
+            colour: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+    pub fn with_colour(mut self, colour: [f32; 4]) -> Self {
+        self.colour = colour;
+        self
+    }
+}
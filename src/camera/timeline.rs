@@ -0,0 +1,439 @@
+use crate::{asset::LoadError, camera::ParallaxCamera, easing::Easing};
+use glam::Vec3;
+use hecs::World;
+use serde::Deserialize;
+use std::{
+    f32::consts::PI,
+    fs::read_to_string,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+// Relative change in a camera field below which it is taken as float round-off rather than
+// another system moving the camera.
+const POSE_TOLERANCE: f32 = 1e-4;
+
+/// A pose the camera passes through. Fields left as `None` keep whatever the gameplay camera
+/// is doing.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CameraKeyframe {
+    /// Seconds from the start of the timeline.
+    pub time: f32,
+    pub eye: Vec3,
+    #[serde(default)]
+    pub look_dir: Option<Vec3>,
+    #[serde(default)]
+    pub fov_y: Option<f32>,
+    /// Smoothly interpolated zooms want the camera in `ZoomMode::Smooth`.
+    #[serde(default)]
+    pub zoom: Option<f32>,
+    /// Easing of the segment arriving at this keyframe.
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+/// Takes over a [`ParallaxCamera`] and moves it through a list of keyframes, the eye following
+/// a Catmull-Rom spline through them. Control is blended in from and back out to whatever the
+/// other camera systems are doing, so add [`play_camera_timeline`] after them.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CameraTimeline {
+    pub keyframes: Vec<CameraKeyframe>,
+    /// Seconds spent blending from the gameplay camera into the timeline.
+    #[serde(default)]
+    pub blend_in: f32,
+    /// Seconds spent blending back to the gameplay camera after the last keyframe.
+    #[serde(default)]
+    pub blend_out: f32,
+    #[serde(skip)]
+    playing: bool,
+    #[serde(skip)]
+    playback: Option<Playback>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct CameraPose {
+    eye: Vec3,
+    look_dir: Vec3,
+    fov_y: f32,
+    zoom: f32,
+}
+
+#[derive(Clone, Debug)]
+struct Playback {
+    time: f32,
+    // The pose the other camera systems want, handed back to at the end.
+    gameplay: CameraPose,
+    // The pose written to the camera last frame.
+    applied: CameraPose,
+}
+
+impl CameraTimeline {
+    pub fn new(mut keyframes: Vec<CameraKeyframe>, blend_in: f32, blend_out: f32) -> Self {
+        sort_keyframes(&mut keyframes);
+        CameraTimeline {
+            keyframes,
+            blend_in,
+            blend_out,
+            playing: false,
+            playback: None,
+        }
+    }
+    /// Loads a timeline from a RON file. The timeline does not play until [`Self::play`] is
+    /// called.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let src = read_to_string(path)?;
+        let mut timeline: CameraTimeline =
+            ron::de::from_str(&src).map_err(|err| LoadError::Parse(err.to_string()))?;
+        sort_keyframes(&mut timeline.keyframes);
+        Ok(timeline)
+    }
+    /// Starts, or restarts, the timeline from the first keyframe.
+    pub fn play(&mut self) {
+        self.playing = true;
+        self.playback = None;
+    }
+    /// Hands control back to the gameplay camera straight away.
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.playback = None;
+    }
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map(|key| key.time).unwrap_or(0.0)
+    }
+
+    fn sample(&self, time: f32, gameplay: &CameraPose) -> CameraPose {
+        let keys = &self.keyframes;
+        let pose = |key: &CameraKeyframe| CameraPose {
+            eye: key.eye,
+            look_dir: key.look_dir.unwrap_or(gameplay.look_dir),
+            fov_y: key.fov_y.unwrap_or(gameplay.fov_y),
+            zoom: key.zoom.unwrap_or(gameplay.zoom),
+        };
+
+        let last = keys.len() - 1;
+        if last == 0 || time <= keys[0].time {
+            return pose(&keys[0]);
+        }
+        if time >= keys[last].time {
+            return pose(&keys[last]);
+        }
+
+        let next = keys
+            .iter()
+            .position(|key| key.time > time)
+            .unwrap_or(last);
+        let prev = next - 1;
+
+        let span = keys[next].time - keys[prev].time;
+        let t = if span > 0.0 {
+            keys[next].easing.apply((time - keys[prev].time) / span)
+        } else {
+            1.0
+        };
+
+        let (a, b) = (pose(&keys[prev]), pose(&keys[next]));
+        let before = keys[prev.saturating_sub(1)].eye;
+        let after = keys[(next + 1).min(last)].eye;
+
+        CameraPose {
+            eye: catmull_rom(before, a.eye, b.eye, after, t),
+            look_dir: slerp(a.look_dir, b.look_dir, t),
+            fov_y: a.fov_y + (b.fov_y - a.fov_y) * t,
+            zoom: a.zoom + (b.zoom - a.zoom) * t,
+        }
+    }
+
+    // How much of the timeline pose to use at `time`, ramping in and out at either end.
+    fn weight(&self, time: f32) -> f32 {
+        let blend_in = if self.blend_in > 0.0 {
+            time / self.blend_in
+        } else {
+            1.0
+        };
+        let remaining = self.duration() + self.blend_out - time;
+        let blend_out = if self.blend_out > 0.0 {
+            remaining / self.blend_out
+        } else if remaining >= 0.0 {
+            1.0
+        } else {
+            0.0
+        };
+        Easing::EaseInOut.apply(blend_in.min(blend_out))
+    }
+}
+
+impl CameraPose {
+    fn of(cam: &ParallaxCamera) -> Self {
+        CameraPose {
+            eye: cam.eye,
+            look_dir: cam.look_dir,
+            fov_y: cam.fov_y,
+            zoom: cam.zoom,
+        }
+    }
+    fn apply(&self, cam: &mut ParallaxCamera) {
+        cam.eye = self.eye;
+        cam.look_dir = self.look_dir;
+        cam.fov_y = self.fov_y;
+        cam.zoom = self.zoom;
+    }
+    fn lerp(&self, other: &CameraPose, t: f32) -> CameraPose {
+        CameraPose {
+            eye: self.eye.lerp(other.eye, t),
+            look_dir: slerp(self.look_dir, other.look_dir, t),
+            fov_y: self.fov_y + (other.fov_y - self.fov_y) * t,
+            zoom: self.zoom + (other.zoom - self.zoom) * t,
+        }
+    }
+}
+
+/// Advances every playing [`CameraTimeline`] and writes the blended pose to its camera.
+pub fn play_camera_timeline(world: &World, dt: Duration, _instant: Instant) {
+    let dt = dt.as_secs_f32();
+    let mut q = world.query::<(&mut ParallaxCamera, &mut CameraTimeline)>();
+
+    for (_, (cam, timeline)) in q.iter() {
+        if !timeline.playing || timeline.keyframes.is_empty() {
+            continue;
+        }
+
+        let current = CameraPose::of(cam);
+        let mut playback = timeline.playback.take().unwrap_or(Playback {
+            time: 0.0,
+            gameplay: current,
+            applied: current,
+        });
+
+        // Anything that differs from what was written last frame was changed by another
+        // system, and becomes the pose to hand back to.
+        let applied = playback.applied;
+        if changed((current.eye - applied.eye).length(), applied.eye.length()) {
+            playback.gameplay.eye = current.eye;
+        }
+        if changed((current.look_dir - applied.look_dir).length(), 1.0) {
+            playback.gameplay.look_dir = current.look_dir;
+        }
+        if changed(current.fov_y - applied.fov_y, applied.fov_y) {
+            playback.gameplay.fov_y = current.fov_y;
+        }
+        if changed(current.zoom - applied.zoom, applied.zoom) {
+            playback.gameplay.zoom = current.zoom;
+        }
+
+        playback.time += dt;
+
+        if playback.time >= timeline.duration() + timeline.blend_out {
+            playback.gameplay.apply(cam);
+            timeline.playing = false;
+            continue;
+        }
+
+        let pose = timeline.sample(playback.time, &playback.gameplay);
+        let blended = playback.gameplay.lerp(&pose, timeline.weight(playback.time));
+        blended.apply(cam);
+        playback.applied = blended;
+        timeline.playback = Some(playback);
+    }
+}
+
+fn sort_keyframes(keyframes: &mut Vec<CameraKeyframe>) {
+    keyframes.sort_by(|a, b| {
+        a.time
+            .partial_cmp(&b.time)
+            .expect("keyframe times are not NaN")
+    });
+}
+
+// Whether a field of `size` that moved by `difference` changed by more than round-off.
+fn changed(difference: f32, size: f32) -> bool {
+    difference.abs() > POSE_TOLERANCE * size.abs().max(1.0)
+}
+
+// Turns from direction `a` to `b` at a steady rate, keeping unit length even when they point
+// opposite ways.
+fn slerp(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    let (a, b) = (a.normalize(), b.normalize());
+    let cos = a.dot(b).max(-1.0).min(1.0);
+    if cos > 1.0 - POSE_TOLERANCE {
+        return a.lerp(b, t).normalize();
+    }
+    // Any way round is as short as any other, so turn through a perpendicular.
+    let (b, angle) = if cos < -1.0 + POSE_TOLERANCE {
+        let axis = if a.x.abs() < 0.9 {
+            Vec3::unit_x()
+        } else {
+            Vec3::unit_y()
+        };
+        (a.cross(axis).normalize(), PI)
+    } else {
+        ((b - a * cos).normalize(), cos.acos())
+    };
+    a * (angle * t).cos() + b * (angle * t).sin()
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * ((2.0 * p1)
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn key(time: f32, eye: Vec3) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            eye,
+            look_dir: None,
+            fov_y: None,
+            zoom: None,
+            easing: Easing::Linear,
+        }
+    }
+
+    fn gameplay() -> CameraPose {
+        CameraPose {
+            eye: Vec3::new(0.0, 0.0, -5.0),
+            look_dir: Vec3::unit_z(),
+            fov_y: 1.0,
+            zoom: 1.0,
+        }
+    }
+
+    #[test]
+    fn samples_through_the_keyframes() {
+        // Out of order, to be sorted.
+        let timeline = CameraTimeline::new(
+            vec![
+                key(2.0, Vec3::new(20.0, 0.0, 0.0)),
+                key(0.0, Vec3::zero()),
+                key(3.0, Vec3::new(30.0, 0.0, 0.0)),
+                key(1.0, Vec3::new(10.0, 0.0, 0.0)),
+            ],
+            0.0,
+            0.0,
+        );
+        let gameplay = gameplay();
+
+        assert_eq!(timeline.sample(-1.0, &gameplay).eye, Vec3::zero());
+        assert_eq!(
+            timeline.sample(4.0, &gameplay).eye,
+            Vec3::new(30.0, 0.0, 0.0)
+        );
+        let mid = timeline.sample(1.5, &gameplay);
+        assert!((mid.eye - Vec3::new(15.0, 0.0, 0.0)).length() < EPSILON);
+        // What the keyframes leave out comes from the gameplay camera.
+        assert_eq!(mid.look_dir, gameplay.look_dir);
+        assert_eq!(mid.fov_y, gameplay.fov_y);
+        assert_eq!(mid.zoom, gameplay.zoom);
+    }
+
+    #[test]
+    fn turns_between_opposite_look_directions() {
+        let mut back = key(1.0, Vec3::zero());
+        back.look_dir = Some(-Vec3::unit_z());
+        let mut front = key(0.0, Vec3::zero());
+        front.look_dir = Some(Vec3::unit_z());
+        let timeline = CameraTimeline::new(vec![front, back], 0.0, 0.0);
+
+        for &time in [0.25, 0.5, 0.75].iter() {
+            let look_dir = timeline.sample(time, &gameplay()).look_dir;
+            assert!((look_dir.length() - 1.0).abs() < EPSILON, "{:?}", look_dir);
+            let angle = look_dir.dot(Vec3::unit_z()).max(-1.0).min(1.0).acos();
+            assert!(
+                (angle - PI * time).abs() < EPSILON,
+                "{:?} at {}",
+                look_dir,
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn slerp_turns_at_a_steady_rate() {
+        let half = slerp(Vec3::unit_x(), Vec3::unit_y() * 3.0, 0.5);
+        assert!((half - Vec3::new(1.0, 1.0, 0.0).normalize()).length() < EPSILON);
+        assert_eq!(slerp(Vec3::unit_x(), Vec3::unit_x(), 0.3), Vec3::unit_x());
+    }
+
+    #[test]
+    fn blends_in_and_out() {
+        let timeline = CameraTimeline::new(
+            vec![key(0.0, Vec3::zero()), key(2.0, Vec3::zero())],
+            1.0,
+            1.0,
+        );
+        let weights: Vec<f32> = [0.0, 0.5, 1.0, 2.0, 2.5, 3.0]
+            .iter()
+            .map(|&time| timeline.weight(time))
+            .collect();
+        assert_eq!(weights, vec![0.0, 0.5, 1.0, 1.0, 0.5, 0.0]);
+
+        let cut = CameraTimeline::new(vec![key(0.0, Vec3::zero())], 0.0, 0.0);
+        assert_eq!(cut.weight(0.0), 1.0);
+        assert_eq!(cut.weight(0.1), 0.0);
+    }
+
+    #[test]
+    fn hands_control_back_to_gameplay() {
+        let mut world = World::new();
+        let mut flyby = key(0.0, Vec3::new(10.0, 0.0, 0.0));
+        flyby.fov_y = Some(0.5);
+        let mut end = key(1.0, Vec3::new(10.0, 0.0, 0.0));
+        end.fov_y = Some(0.5);
+        let mut timeline = CameraTimeline::new(vec![flyby, end], 0.5, 0.5);
+        timeline.play();
+        let cam = ParallaxCamera::new(Vec3::new(0.0, 0.0, -5.0), Vec3::unit_z(), 1.0, 0.1, 500.0);
+        let entity = world.spawn((cam, timeline));
+
+        let frame = Duration::from_millis(100);
+        for i in 0..100 {
+            {
+                let mut cam = world
+                    .get_mut::<ParallaxCamera>(entity)
+                    .expect("camera exists");
+                // Round-off from other systems rewriting the pose isn't a change.
+                if i > 0 {
+                    cam.eye += Vec3::splat(1e-6);
+                }
+                // But gameplay changing the fov is, and is kept at the end.
+                if i == 3 {
+                    cam.fov_y = 0.8;
+                }
+            }
+            play_camera_timeline(&world, frame, Instant::now());
+
+            let cam = world.get::<ParallaxCamera>(entity).expect("camera exists");
+            if i == 7 {
+                assert!((cam.eye - Vec3::new(10.0, 0.0, 0.0)).length() < EPSILON);
+                assert!((cam.fov_y - 0.5).abs() < EPSILON);
+            }
+            if !world
+                .get::<CameraTimeline>(entity)
+                .expect("timeline exists")
+                .is_playing()
+            {
+                break;
+            }
+        }
+
+        let cam = world.get::<ParallaxCamera>(entity).expect("camera exists");
+        assert!(!world
+            .get::<CameraTimeline>(entity)
+            .expect("timeline exists")
+            .is_playing());
+        assert_eq!(cam.eye, Vec3::new(0.0, 0.0, -5.0));
+        assert_eq!(cam.fov_y, 0.8);
+        assert_eq!(cam.zoom, 1.0);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{camera::ParallaxCamera, gpu_primitives::CameraUniform, Rect};
+use glam::{Mat4, Vec2, Vec4};
+use hecs::Entity;
+
+/// How many sprites the camera this is attached to saw the last time the scene was built.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullStats {
+    pub visible: u32,
+    pub culled: u32,
+}
+
+/// The projections of one camera, collecting stats as sprites are tested against it.
+pub(crate) struct Frustum {
+    pub entity: Entity,
+    pub camera: ParallaxCamera,
+    pub ortho: Mat4,
+    pub persp: Mat4,
+    pub stats: CullStats,
+}
+
+impl Frustum {
+    pub fn new(entity: Entity, camera: ParallaxCamera, uniform: &CameraUniform) -> Self {
+        Frustum {
+            entity,
+            camera,
+            ortho: Mat4::from_cols_array(&uniform.ortho),
+            persp: Mat4::from_cols_array(&uniform.persp),
+            stats: CullStats::default(),
+        }
+    }
+    /// Tests a sprite quad against the frustum and counts the result.
+    pub fn test(&mut self, model: &Mat4, half_extents: Vec2) -> bool {
+        let visible = is_visible(&self.ortho, &self.persp, model, half_extents);
+        if visible {
+            self.stats.visible += 1;
+        } else {
+            self.stats.culled += 1;
+        }
+        visible
+    }
+}
+
+/// The area a sprite quad covers in normalised device coordinates, worked out the same way as
+/// the vertex shader: the orthographic quad moved by the parallax shift of its centre. `None`
+/// when the centre falls outside the depth range.
+pub(crate) fn projected_bounds(
+    ortho: &Mat4,
+    persp: &Mat4,
+    model: &Mat4,
+    half_extents: Vec2,
+) -> Option<Rect> {
+    let centre = *model * Vec4::new(0.0, 0.0, 0.0, 1.0);
+    let p_c = *persp * centre;
+    let o_c = *ortho * centre;
+    if p_c.w <= 0.0 {
+        return None;
+    }
+
+    let p_c_ndc = p_c / p_c.w;
+    let o_c_ndc = o_c / o_c.w;
+    if p_c_ndc.z < 0.0 || p_c_ndc.z > 1.0 {
+        return None;
+    }
+    let d_ndc = Vec2::new(p_c_ndc.x - o_c_ndc.x, p_c_ndc.y - o_c_ndc.y);
+
+    let corners = [
+        Vec2::new(-half_extents.x, -half_extents.y),
+        Vec2::new(half_extents.x, -half_extents.y),
+        Vec2::new(half_extents.x, half_extents.y),
+        Vec2::new(-half_extents.x, half_extents.y),
+    ];
+
+    let mut min = Vec2::splat(f32::INFINITY);
+    let mut max = Vec2::splat(f32::NEG_INFINITY);
+    for corner in corners.iter() {
+        let o_pos = *ortho * (*model * Vec4::new(corner.x, corner.y, 0.0, 1.0));
+        let pos = Vec2::new(o_pos.x / o_pos.w, o_pos.y / o_pos.w) + d_ndc;
+        min = min.min(pos);
+        max = max.max(pos);
+    }
+
+    Some(Rect::new(min, max))
+}
+
+/// Whether any of a sprite quad lands on screen.
+pub(crate) fn is_visible(ortho: &Mat4, persp: &Mat4, model: &Mat4, half_extents: Vec2) -> bool {
+    let screen = Rect::new(Vec2::splat(-1.0), Vec2::one());
+    projected_bounds(ortho, persp, model, half_extents)
+        .map(|bounds| bounds.intersects(&screen))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{Quat, Vec3};
+
+    fn half() -> Vec2 {
+        Vec2::new(0.5, 0.25)
+    }
+
+    fn projections() -> (Mat4, Mat4) {
+        let cam = ParallaxCamera::new(Vec3::zero(), Vec3::unit_z(), 1.0, 0.1, 500.0)
+            .with_focal_distance(20.0);
+        (cam.generate_ortho(), cam.generate_perspective())
+    }
+
+    fn model(centre: Vec3, angle: f32) -> Mat4 {
+        Mat4::from_rotation_translation(Quat::from_rotation_z(angle), centre)
+    }
+
+    fn ndc(projection: &Mat4, point: Vec3) -> Vec2 {
+        let clip = *projection * point.extend(1.0);
+        Vec2::new(clip.x, clip.y) / clip.w
+    }
+
+    // A sprite centre at `depth` whose nearest edge lands `gap` in normalised device
+    // coordinates past the screen edge on `axis` (0 for x, 1 for y) in the direction of `sign`.
+    // Negative gaps put the edge inside the screen.
+    fn past_edge(depth: f32, axis: usize, sign: f32, gap: f32) -> Vec3 {
+        let (ortho, persp) = projections();
+        // Both are linear across the plane with the eye at the origin.
+        let centre_per_metre = ndc(&persp, Vec3::new(1.0, 1.0, depth));
+        let size_per_metre = ndc(&ortho, Vec3::new(1.0, 1.0, depth));
+        let (scale, half) = if axis == 0 {
+            (centre_per_metre.x, half().x * size_per_metre.x)
+        } else {
+            (centre_per_metre.y, half().y * size_per_metre.y)
+        };
+        let across = sign * (1.0 + gap + half) / scale;
+        if axis == 0 {
+            Vec3::new(across, 0.0, depth)
+        } else {
+            Vec3::new(0.0, across, depth)
+        }
+    }
+
+    #[test]
+    fn sprites_just_inside_each_edge_are_visible() {
+        let (ortho, persp) = projections();
+        // In front of, on and behind the focal plane.
+        for &depth in [2.0, 20.0, 400.0].iter() {
+            for &axis in [0, 1].iter() {
+                for &sign in [-1.0, 1.0].iter() {
+                    let inside = model(past_edge(depth, axis, sign, -0.01), 0.0);
+                    assert!(
+                        is_visible(&ortho, &persp, &inside, half()),
+                        "inside axis {} side {} at {}",
+                        axis,
+                        sign,
+                        depth
+                    );
+                    let outside = model(past_edge(depth, axis, sign, 0.01), 0.0);
+                    assert!(
+                        !is_visible(&ortho, &persp, &outside, half()),
+                        "outside axis {} side {} at {}",
+                        axis,
+                        sign,
+                        depth
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sprites_outside_the_depth_range_are_culled() {
+        let (ortho, persp) = projections();
+        for &depth in [-5.0, 600.0].iter() {
+            let centre = model(Vec3::new(0.0, 0.0, depth), 0.0);
+            assert!(projected_bounds(&ortho, &persp, &centre, half()).is_none());
+            assert!(!is_visible(&ortho, &persp, &centre, half()));
+        }
+    }
+
+    #[test]
+    fn rotated_sprites_are_tested_by_their_rotated_corners() {
+        let (ortho, persp) = projections();
+        let long = Vec2::new(2.0, 0.1);
+        // Lying flat it stays above the screen, stood on end it reaches down into it.
+        let centre = past_edge(20.0, 1, 1.0, 0.0) + Vec3::new(0.0, 1.5, 0.0);
+        assert!(!is_visible(&ortho, &persp, &model(centre, 0.0), long));
+        let standing = model(centre, std::f32::consts::FRAC_PI_2);
+        assert!(is_visible(&ortho, &persp, &standing, long));
+
+        // A square turned by 45 degrees covers its diagonal.
+        let square = Vec2::splat(1.0);
+        let flat = projected_bounds(
+            &ortho,
+            &persp,
+            &model(Vec3::new(0.0, 0.0, 20.0), 0.0),
+            square,
+        )
+        .expect("centre is in range");
+        let turned = projected_bounds(
+            &ortho,
+            &persp,
+            &model(Vec3::new(0.0, 0.0, 20.0), std::f32::consts::FRAC_PI_4),
+            square,
+        )
+        .expect("centre is in range");
+        let ratio = turned.size() / flat.size();
+        assert!(
+            (ratio - Vec2::splat(2f32.sqrt())).length() < 1e-4,
+            "{:?}",
+            ratio
+        );
+    }
+}
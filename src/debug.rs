@@ -0,0 +1,276 @@
+use crate::{camera::ParallaxCamera, text::Text, Rect};
+use glam::{Vec2, Vec3};
+use std::f32::consts::PI;
+
+/// Where a debug shape is drawn. World space shapes lie in the x/y plane at a depth, in metres,
+/// and get the parallax of a sprite there. Screen space shapes are in window pixels from the
+/// top left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugSpace {
+    World(f32),
+    Screen,
+}
+
+/// Immediate mode debug drawing. Spawn one, then call its drawing methods from systems every
+/// frame the shapes should show; they are drawn over everything and cleared once the frame is
+/// built. The toggles add shapes for every sprite and camera.
+pub struct DebugDraw {
+    /// Font for labels, none are drawn without one.
+    pub font: Option<String>,
+    /// Outline every sprite quad.
+    pub show_sprite_bounds: bool,
+    /// Mark the position of every sprite.
+    pub show_pivots: bool,
+    /// Label every sprite with its entity id and depth.
+    pub show_labels: bool,
+    /// Draw a metre grid over what each active camera looking down the z axis sees of its
+    /// focal plane.
+    pub show_focal_plane: bool,
+    /// Replace the cameras' views with a map of the first active camera's scene seen from
+    /// above, x across and depth up the screen. World space shapes are drawn through the
+    /// cameras' views, so they go too and only screen space ones stay. The map needs a camera
+    /// looking down the z axis and shows a warning instead for any other.
+    pub show_top_down: bool,
+    pub(crate) lines: Vec<DebugLine>,
+    pub(crate) labels: Vec<DebugLabel>,
+}
+
+pub(crate) struct DebugLine {
+    pub space: DebugSpace,
+    pub from: Vec2,
+    pub to: Vec2,
+    pub colour: [f32; 4],
+}
+
+pub(crate) struct DebugLabel {
+    pub space: DebugSpace,
+    pub position: Vec2,
+    pub text: Text,
+}
+
+pub const RED: [f32; 4] = [1.0, 0.2, 0.2, 1.0];
+pub const GREEN: [f32; 4] = [0.2, 1.0, 0.2, 1.0];
+pub const YELLOW: [f32; 4] = [1.0, 1.0, 0.2, 1.0];
+pub const GREY: [f32; 4] = [0.6, 0.6, 0.6, 1.0];
+
+// Segments used for circles.
+const CIRCLE_SEGMENTS: usize = 24;
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        DebugDraw {
+            font: None,
+            show_sprite_bounds: false,
+            show_pivots: false,
+            show_labels: false,
+            show_focal_plane: false,
+            show_top_down: false,
+            lines: vec![],
+            labels: vec![],
+        }
+    }
+    pub fn with_font(mut self, font: &str) -> Self {
+        self.font = Some(font.to_string());
+        self
+    }
+    pub fn line(&mut self, space: DebugSpace, from: Vec2, to: Vec2, colour: [f32; 4]) {
+        self.lines.push(DebugLine {
+            space,
+            from,
+            to,
+            colour,
+        });
+    }
+    pub fn rect(&mut self, space: DebugSpace, rect: Rect, colour: [f32; 4]) {
+        let corners = [
+            rect.min,
+            Vec2::new(rect.max.x, rect.min.y),
+            rect.max,
+            Vec2::new(rect.min.x, rect.max.y),
+        ];
+        self.polygon(space, &corners, colour);
+    }
+    /// Joins `points` with lines, and the last back to the first.
+    pub fn polygon(&mut self, space: DebugSpace, points: &[Vec2], colour: [f32; 4]) {
+        for (i, &from) in points.iter().enumerate() {
+            let to = points[(i + 1) % points.len()];
+            self.line(space, from, to, colour);
+        }
+    }
+    pub fn circle(&mut self, space: DebugSpace, centre: Vec2, radius: f32, colour: [f32; 4]) {
+        let points: Vec<Vec2> = (0..CIRCLE_SEGMENTS)
+            .map(|i| {
+                let angle = i as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * PI;
+                centre + Vec2::new(angle.cos(), angle.sin()) * radius
+            })
+            .collect();
+        self.polygon(space, &points, colour);
+    }
+    /// An x shaped cross `size` across.
+    pub fn cross(&mut self, space: DebugSpace, centre: Vec2, size: f32, colour: [f32; 4]) {
+        let half = size / 2.0;
+        self.line(
+            space,
+            centre - Vec2::splat(half),
+            centre + Vec2::splat(half),
+            colour,
+        );
+        self.line(
+            space,
+            centre + Vec2::new(-half, half),
+            centre + Vec2::new(half, -half),
+            colour,
+        );
+    }
+    /// Text with its top left at `position`, in the debug font.
+    pub fn text(&mut self, space: DebugSpace, position: Vec2, text: &str, colour: [f32; 4]) {
+        if let Some(font) = &self.font {
+            self.labels.push(DebugLabel {
+                space,
+                position,
+                text: Text::new(font, text).with_colour(colour),
+            });
+        }
+    }
+}
+
+impl Default for DebugDraw {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Depths the top-down view's frustum curves are sampled at.
+const FRUSTUM_SAMPLES: usize = 32;
+// Pixels between the top-down map and the edge of the screen.
+const MAP_MARGIN: f32 = 32.0;
+
+/// Places x and depth on the top-down map, which fills a screen `screen` pixels big. Depth is
+/// on a square root scale so the layers near the camera, which move the most, are spread out.
+struct TopDownMap {
+    area: Rect,
+    centre_x: f32,
+    half_width: f32,
+    near: f32,
+    far: f32,
+}
+
+impl TopDownMap {
+    /// Fits everything between the perspective camera and the far plane of `cam`.
+    fn new(cam: &ParallaxCamera, screen: Vec2) -> Self {
+        let near = cam.eye.z.min(cam.eye.z - cam.perspective_offset());
+        let far = cam.eye.z + cam.far;
+        let widest = cam.visible_region(cam.far, Vec2::zero());
+        TopDownMap {
+            area: Rect::new(Vec2::splat(MAP_MARGIN), screen - Vec2::splat(MAP_MARGIN)),
+            centre_x: cam.eye.x,
+            half_width: (widest.max.x - cam.eye.x)
+                .max(cam.eye.x - widest.min.x)
+                .max(1.0),
+            near,
+            far,
+        }
+    }
+    fn point(&self, x: f32, z: f32) -> Vec2 {
+        let across = ((x - self.centre_x) / self.half_width + 1.0) / 2.0;
+        let up = ((z - self.near) / (self.far - self.near)).max(0.0).sqrt();
+        let size = self.area.size();
+        Vec2::new(
+            self.area.min.x + across * size.x,
+            self.area.max.y - up * size.y,
+        )
+    }
+}
+
+/// Draws the top-down view of `cam`: its position, near, focal and far planes, and its
+/// orthographic and perspective frustums, with a mark for each sprite in `sprites`, given by
+/// its centre and half its width, and a line across for each repeating layer in `layers`.
+pub(crate) fn draw_top_down(
+    debug: &mut DebugDraw,
+    cam: &ParallaxCamera,
+    screen: Vec2,
+    sprites: &[(Vec3, f32)],
+    layers: &[f32],
+) {
+    // Depth is measured along z, which only lines up with the camera when it looks that way.
+    if !cam.looks_along_z() {
+        debug.text(
+            DebugSpace::Screen,
+            Vec2::splat(MAP_MARGIN),
+            "top-down view needs a camera looking along z",
+            RED,
+        );
+        return;
+    }
+    let map = TopDownMap::new(cam, screen);
+    let left = map.centre_x - map.half_width;
+    let right = map.centre_x + map.half_width;
+
+    let planes = [
+        ("near", cam.eye.z + cam.near),
+        ("focal", cam.eye.z + cam.focal_distance),
+        ("far", cam.eye.z + cam.far),
+    ];
+    for &(name, z) in planes.iter() {
+        let from = map.point(left, z);
+        debug.line(DebugSpace::Screen, from, map.point(right, z), GREY);
+        debug.text(DebugSpace::Screen, from + Vec2::new(4.0, 2.0), name, GREY);
+    }
+
+    for &z in layers {
+        debug.line(
+            DebugSpace::Screen,
+            map.point(left, z),
+            map.point(right, z),
+            YELLOW,
+        );
+    }
+
+    for &(centre, half_width) in sprites {
+        let from = map.point(centre.x - half_width, centre.z);
+        let to = map.point(centre.x + half_width, centre.z);
+        debug.line(DebugSpace::Screen, from, to, GREEN);
+        debug.cross(
+            DebugSpace::Screen,
+            map.point(centre.x, centre.z),
+            6.0,
+            GREEN,
+        );
+    }
+
+    // The orthographic frustum is the same width all the way along.
+    let (width, _) = cam.ortho_size();
+    for &x in [cam.eye.x - width / 2.0, cam.eye.x + width / 2.0].iter() {
+        let from = map.point(x, cam.eye.z + cam.near);
+        debug.line(
+            DebugSpace::Screen,
+            from,
+            map.point(x, cam.eye.z + cam.far),
+            GREY,
+        );
+    }
+
+    // The perspective frustum's edges, curved by the depth scale.
+    let origin = cam.eye.z - cam.perspective_offset();
+    let (mut lefts, mut rights) = (vec![], vec![]);
+    for i in 0..=FRUSTUM_SAMPLES {
+        let z = origin + (cam.eye.z + cam.far - origin) * i as f32 / FRUSTUM_SAMPLES as f32;
+        let region = cam.visible_region(z - cam.eye.z, Vec2::zero());
+        lefts.push(map.point(region.min.x, z));
+        rights.push(map.point(region.max.x, z));
+    }
+    for edge in [lefts, rights].iter() {
+        for pair in edge.windows(2) {
+            debug.line(DebugSpace::Screen, pair[0], pair[1], RED);
+        }
+    }
+
+    let eye = map.point(cam.eye.x, cam.eye.z);
+    debug.circle(DebugSpace::Screen, eye, 8.0, RED);
+    debug.text(
+        DebugSpace::Screen,
+        eye + Vec2::new(12.0, -4.0),
+        "camera",
+        RED,
+    );
+}
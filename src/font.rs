@@ -0,0 +1,71 @@
+use crate::{
+    asset::{FontAsset, LoadError},
+    quad::{Binding, Placement, QuadBatchBuilder},
+    renderer::TEXTURE_ARRAY_SIZE,
+    sprite::texture_array_bind_group,
+    text::{layout, load_font, FontMetrics, Text},
+    texture::ArrayTexture,
+};
+use wgpu::TextureView;
+
+pub struct Font {
+    pub id: String,
+    metrics: FontMetrics,
+    pub bind_group: wgpu::BindGroup,
+    // The textures behind the bind group.
+    _pages: Vec<ArrayTexture>,
+}
+
+impl Font {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sprite_bind_group_layout: &wgpu::BindGroupLayout,
+        asset: &FontAsset,
+    ) -> Result<Self, LoadError> {
+        let (metrics, pages) = load_font(asset)?;
+        if pages.is_empty() || pages.len() > TEXTURE_ARRAY_SIZE as usize {
+            return Err(LoadError::Parse(format!(
+                "font {} has {} pages",
+                asset.id,
+                pages.len()
+            )));
+        }
+
+        let pages: Vec<ArrayTexture> = pages
+            .into_iter()
+            .map(|page| ArrayTexture::new(device, queue, page))
+            .collect();
+        let views: Vec<&TextureView> = pages.iter().map(|page| &page.view).collect();
+        let bind_group = texture_array_bind_group(device, sprite_bind_group_layout, &views);
+
+        Ok(Font {
+            id: asset.id.clone(),
+            metrics,
+            bind_group,
+            _pages: pages,
+        })
+    }
+    /// Lays out `text` and adds its glyphs, in font pixels, to `batch`. `binding` is this font.
+    pub fn push_text(
+        &self,
+        batch: &mut QuadBatchBuilder,
+        binding: Binding,
+        text: &Text,
+        placement: &Placement,
+    ) {
+        for placed in layout(&self.metrics, text) {
+            let glyph = placed.glyph;
+            let min = placed.position;
+            let max = min + glyph.size;
+            batch.push(
+                binding,
+                placement,
+                [min.x, min.y, max.x, max.y],
+                glyph.uv,
+                glyph.page,
+                placed.colour,
+            );
+        }
+    }
+}
@@ -1,6 +1,7 @@
-use glam::{Quat, Vec3};
+use crate::{camera::atmosphere::MAX_COLOUR_BANDS, light::MAX_LIGHTS};
+use glam::{Quat, Vec2, Vec3};
 
-pub type Index = u16;
+pub type Index = u32;
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -31,11 +32,92 @@ impl Vertex {
     }
 }
 
+/// A corner of a glyph or UI quad. World space quads are shifted for parallax around their
+/// anchor the way a sprite is around its centre.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct QuadVertex {
+    /// From the anchor in metres, or the position in normalised device coordinates for screen
+    /// space quads.
+    pub offset: [f32; 4],
+    pub tex_coord: [f32; 2],
+    /// World position the quad hangs from, w is zero for screen space quads.
+    pub anchor: [f32; 4],
+    pub colour: [f32; 4],
+    /// Font page or sprite frame.
+    pub page: u32,
+}
+
+impl QuadVertex {
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<QuadVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 10]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 14]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Uint,
+                },
+            ],
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     pub ortho: [f32; 16],
     pub persp: [f32; 16],
+    /// Position of the eye, w unused.
+    pub eye: [f32; 4],
+    /// Normalised look direction, w unused.
+    pub look: [f32; 4],
+    /// Fog colour, with the most it covers in alpha.
+    pub fog_colour: [f32; 4],
+    /// Fog start, linear end, exponential density, and mode: 0 for none, 1 for linear and 2
+    /// for exponential.
+    pub fog_params: [f32; 4],
+    /// Near and far depth of each colour band in x and y.
+    pub band_ranges: [[f32; 4]; MAX_COLOUR_BANDS],
+    /// Tint of each colour band, and its desaturation in w.
+    pub band_grades: [[f32; 4]; MAX_COLOUR_BANDS],
+    /// Number of colour bands in use in x.
+    pub band_count: [u32; 4],
+    /// Ambient light multiplied with every sprite, w unused.
+    pub ambient: [f32; 4],
+    /// Number of lights in use in x.
+    pub light_count: [u32; 4],
+    /// Position of each light, and its radius in w.
+    pub light_positions: [[f32; 4]; MAX_LIGHTS],
+    /// Colour of each light times its intensity, w unused.
+    pub light_colours: [[f32; 4]; MAX_LIGHTS],
+    /// Direction of each spot light in x and y, and the cosines of its outer and inner
+    /// angles. Zero for point lights.
+    pub light_spots: [[f32; 4]; MAX_LIGHTS],
+    /// Height and depth range of each light.
+    pub light_params: [[f32; 4]; MAX_LIGHTS],
 }
 
 pub struct Instance {
@@ -43,6 +125,7 @@ pub struct Instance {
     pub rotation: Quat,
     pub scale: f32,
     pub frame_id: u32,
+    pub uv_offset: Vec2,
 }
 
 #[repr(C)]
@@ -50,16 +133,23 @@ pub struct Instance {
 pub struct InstanceRaw {
     model: [[f32; 4]; 4],
     frame_id: u32,
+    uv_offset: [f32; 2],
+}
+
+impl Instance {
+    pub fn model(&self) -> glam::Mat4 {
+        glam::Mat4::from_translation(self.position)
+            * glam::Mat4::from_quat(self.rotation)
+            * glam::Mat4::from_scale(Vec3::splat(self.scale))
+    }
 }
 
 impl From<Instance> for InstanceRaw {
     fn from(from: Instance) -> Self {
         InstanceRaw {
-            model: (glam::Mat4::from_translation(from.position)
-                * glam::Mat4::from_quat(from.rotation)
-                * glam::Mat4::from_scale(Vec3::splat(from.scale)))
-            .to_cols_array_2d(),
+            model: from.model().to_cols_array_2d(),
             frame_id: from.frame_id,
+            uv_offset: [from.uv_offset.x, from.uv_offset.y],
         }
     }
 }
@@ -96,6 +186,11 @@ impl InstanceRaw {
                     shader_location: 6,
                     format: wgpu::VertexFormat::Uint,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 17]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                    format: wgpu::VertexFormat::Float2,
+                },
             ],
         }
     }
@@ -0,0 +1,83 @@
+use crate::camera::ParallaxCamera;
+use glam::Vec2;
+use std::ops::RangeInclusive;
+
+const MAX_TILES: usize = 1024;
+
+/// Tiles a sprite across the x/y plane at the depth of the entity's `Position`, which anchors
+/// one of the tiles. Only the tiles some camera can see are drawn, so one entity covers a level
+/// of any length. Needs a `Position` and a `Scale`, but no `Sprite`.
+pub struct RepeatingLayer {
+    pub sprite: String,
+    pub frame_id: u32,
+    /// Distance between tile centres in metres. Defaults to the scaled size of the sprite so
+    /// tiles sit edge to edge.
+    pub spacing: Option<Vec2>,
+    /// Also repeat up and down, for things like a starfield. Otherwise only one row is drawn.
+    pub repeat_y: bool,
+}
+
+impl RepeatingLayer {
+    pub fn new(sprite: &str) -> Self {
+        RepeatingLayer {
+            sprite: sprite.to_string(),
+            frame_id: 0,
+            spacing: None,
+            repeat_y: false,
+        }
+    }
+    pub fn with_spacing(mut self, spacing: Vec2) -> Self {
+        self.spacing = Some(spacing);
+        self
+    }
+    pub fn with_repeat_y(mut self, repeat_y: bool) -> Self {
+        self.repeat_y = repeat_y;
+        self
+    }
+
+    /// Column and row indices of the tiles `cam` can see, for tiles with `half_extents`
+    /// repeated every `spacing` from `anchor` at `depth`.
+    pub(crate) fn visible_tiles(
+        &self,
+        cam: &ParallaxCamera,
+        anchor: Vec2,
+        depth: f32,
+        spacing: Vec2,
+        half_extents: Vec2,
+    ) -> Vec<(i32, i32)> {
+        let region = cam.visible_region(depth - cam.eye.z, half_extents);
+        let columns = tile_range(region.min.x - anchor.x, region.max.x - anchor.x, spacing.x);
+        let rows = if self.repeat_y {
+            tile_range(region.min.y - anchor.y, region.max.y - anchor.y, spacing.y)
+        } else if region.min.y <= anchor.y && anchor.y <= region.max.y {
+            0..=0
+        } else {
+            return vec![];
+        };
+
+        let mut tiles = vec![];
+        for row in rows {
+            for column in columns.clone() {
+                // Only a spacing far too small for the layer's depth gets here, stop before it
+                // fills the instance buffer with tiles too small to see.
+                if tiles.len() >= MAX_TILES {
+                    log::warn!(
+                        "repeating layer {} has too many tiles on screen",
+                        self.sprite
+                    );
+                    return tiles;
+                }
+                tiles.push((column, row));
+            }
+        }
+        tiles
+    }
+}
+
+// Indices of the tiles with centres between `min` and `max`, relative to the anchor tile.
+fn tile_range(min: f32, max: f32, spacing: f32) -> RangeInclusive<i32> {
+    if spacing <= 0.0 {
+        return 0..=0;
+    }
+    (min / spacing).ceil() as i32..=(max / spacing).floor() as i32
+}
@@ -1,34 +1,73 @@
 #![feature(in_band_lifetimes)]
 
 use crate::{
-    camera::{ActiveCamera, Camera, ParallaxCamera},
+    app::WINDOW_SIZE,
+    asset::{FontAsset, SpriteAsset},
+    camera::{
+        atmosphere::Atmosphere, shake::CameraShake, sky::Sky, ActiveCamera, Camera, ParallaxCamera,
+        RenderTarget, Viewport,
+    },
+    culling::{CullStats, Frustum},
+    debug::{draw_top_down, DebugDraw, DebugLabel, DebugLine, DebugSpace, GREEN, RED, YELLOW},
     gpu_primitives::{Instance, InstanceRaw},
-    scene::Scene,
+    layer::RepeatingLayer,
+    light::{apply_lights, Light},
+    scene::{AssetChange, Scene, SizedSprite, TextItem, UiContent, UiItem, View},
+    scroll::UvScroll,
+    spatial::{world_bounds, SpatialIndex},
+    sprite::half_extents,
+    streaming::ChunkStreamer,
+    text::{Text, TextSpace},
     time::Timer,
+    time_of_day::TimeOfDay,
+    ui::{update_interactions, Mouse, UiImage, UiNode},
 };
-use glam::{Quat, Vec3};
+use glam::{Quat, Vec2, Vec3};
 use hecs::{DynamicBundle, Entity, World};
 use std::{
     collections::HashMap,
     time::{Duration, Instant},
 };
-use winit::event::WindowEvent;
+use winit::event::{ElementState, MouseButton, WindowEvent};
 
 pub mod app;
 pub mod asset;
 pub mod camera;
+pub mod culling;
+pub mod debug;
+pub mod easing;
+mod font;
 mod gpu_primitives;
+pub mod layer;
+pub mod light;
+mod quad;
+mod rect;
 mod renderer;
 mod scene;
+pub mod scroll;
+pub mod spatial;
 mod sprite;
+pub mod streaming;
+pub mod text;
 mod texture;
+pub mod tiled;
 mod time;
+pub mod time_of_day;
+pub mod ui;
 
 pub use app::App;
+pub use rect::Rect;
+
+// Corners of a sprite quad in units of its half extents, going round from the bottom left.
+const QUAD_CORNERS: [(f32, f32); 4] = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
 
 pub struct Position(pub Vec3);
 pub struct Rotation(pub Quat);
 pub struct Scale(pub u8);
+/// Draws a `Sprite` stretched to this size in metres, before `Scale`, instead of the size of its
+/// image. Nine-slice sprites keep their borders at `Scale` pixels per image pixel. Sized sprites
+/// aren't rotated.
+pub struct SpriteSize(pub Vec2);
 pub struct KeyboardInput(pub Option<winit::event::KeyboardInput>);
 
 pub struct Sprite {
@@ -49,22 +88,51 @@ pub struct Game<'a> {
     world: World,
     timer: Timer,
     systems: Vec<&'a dyn Fn(&World, Duration, Instant)>,
+    sprite_sizes: HashMap<String, (u32, u32)>,
+    spatial_index: Entity,
+    streamer: Option<ChunkStreamer>,
+    asset_changes: Vec<AssetChange>,
+    mouse: Entity,
+    // In physical pixels, which is what the UI is laid out in.
+    screen_size: (u32, u32),
 }
 
 impl<'a> Game<'a> {
     pub fn new() -> Game<'a> {
+        let mut world = World::new();
+        let spatial_index = world.spawn((SpatialIndex::new(8.0, 10.0),));
+        let mouse = world.spawn((Mouse::default(),));
         Game {
-            world: Default::default(),
+            world,
             timer: Default::default(),
             systems: vec![],
+            sprite_sizes: Default::default(),
+            spatial_index,
+            streamer: None,
+            asset_changes: vec![],
+            mouse,
+            screen_size: (WINDOW_SIZE.width, WINDOW_SIZE.height),
         }
     }
     fn run(&mut self) -> Scene {
         self.timer.tick();
+        self.update_ui();
         for system in self.systems.iter() {
             system(&self.world, self.timer.elapsed(), self.timer.now())
         }
-        self.build_scene()
+        self.stream_chunks();
+        self.update_spatial_index();
+        let scene = self.build_scene();
+
+        let mut mouse = self
+            .world
+            .get_mut::<Mouse>(self.mouse)
+            .expect("mouse exists");
+        mouse.left_pressed = false;
+        mouse.left_released = false;
+        drop(mouse);
+
+        scene
     }
     pub fn spawn_entity(&mut self, components: impl DynamicBundle) -> Entity {
         self.world.spawn(components)
@@ -72,39 +140,503 @@ impl<'a> Game<'a> {
     pub fn add_system(&mut self, system: &'a dyn Fn(&World, Duration, Instant)) {
         self.systems.push(system)
     }
-    fn build_scene(&mut self) -> Scene {
-        let mut sprites: HashMap<String, Vec<InstanceRaw>> = HashMap::default();
+    /// Loads a font for [`Text`] to use, ready from the next frame.
+    pub fn load_font(&mut self, font: FontAsset) {
+        self.asset_changes.push(AssetChange::LoadFont(font));
+    }
+    fn register_sprite_assets(&mut self, assets: &[SpriteAsset]) {
+        for asset in assets {
+            self.sprite_sizes.insert(asset.id.clone(), asset.size());
+        }
+    }
+    fn target_size(&self, target: &RenderTarget) -> Option<(u32, u32)> {
+        match target {
+            RenderTarget::Screen => Some(self.screen_size),
+            RenderTarget::Texture(id) => self.sprite_sizes.get(id).copied(),
+        }
+    }
+    /// Streams the chunks of a level in and out around the active cameras.
+    pub fn stream_world(&mut self, streamer: ChunkStreamer) {
+        self.streamer = Some(streamer);
+    }
+    fn stream_chunks(&mut self) {
+        let streamer = match self.streamer.as_mut() {
+            Some(streamer) => streamer,
+            None => return,
+        };
+        let cameras: Vec<ParallaxCamera> = self
+            .world
+            .query::<(&ActiveCamera, &ParallaxCamera)>()
+            .iter()
+            .map(|(_, (_, cam))| *cam)
+            .collect();
+        streamer.update(
+            &mut self.world,
+            &cameras,
+            &mut self.sprite_sizes,
+            &mut self.asset_changes,
+        );
+    }
+    fn screen_size(&self) -> Vec2 {
+        Vec2::new(self.screen_size.0 as f32, self.screen_size.1 as f32)
+    }
+    fn update_ui(&mut self) {
+        let mouse = *self.world.get::<Mouse>(self.mouse).expect("mouse exists");
+        update_interactions(&self.world, &mouse, self.screen_size());
+    }
+    fn update_spatial_index(&mut self) {
+        let mut index = self
+            .world
+            .get_mut::<SpatialIndex>(self.spatial_index)
+            .expect("spatial index exists");
 
-        for (_, (pos, rot, scale, sprite)) in &mut self
+        index.begin_update();
+        for (entity, (pos, rot, scale, sprite, sized)) in self
             .world
-            .query::<(&Position, &Rotation, &Scale, &Sprite)>()
+            .query::<(&Position, &Rotation, &Scale, &Sprite, Option<&SpriteSize>)>()
+            .iter()
         {
-            let instance_raw = InstanceRaw::from(Instance {
-                position: pos.0,
-                rotation: rot.0,
-                scale: scale.0 as f32,
-                frame_id: sprite.frame_id,
-            });
-            if let Some(instances) = sprites.get(&sprite.id) {
-                // TODO: try and remove these clones
-                let mut new = instances.clone();
-                new.push(instance_raw);
-                sprites.insert(sprite.id.clone(), new);
-            } else {
-                sprites.insert(sprite.id.clone(), vec![instance_raw]);
+            if let Some(half) = self.sprite_half_extents(sprite, sized) {
+                let model = Instance {
+                    position: pos.0,
+                    rotation: rot.0,
+                    scale: scale.0 as f32,
+                    frame_id: sprite.frame_id,
+                    uv_offset: Vec2::zero(),
+                }
+                .model();
+                index.insert(entity, world_bounds(&model, half), pos.0.z);
             }
         }
+        index.sweep();
+    }
+    // Before scaling, `None` if the sprite's asset isn't loaded.
+    fn sprite_half_extents(&self, sprite: &Sprite, sized: Option<&SpriteSize>) -> Option<Vec2> {
+        match sized {
+            Some(size) => Some(size.0 / 2.0),
+            None => self
+                .sprite_sizes
+                .get(&sprite.id)
+                .map(|&size| half_extents(size)),
+        }
+    }
+    fn build_scene(&mut self) -> Scene {
+        let mut views = vec![];
+        let mut frustums = vec![];
+        let lighting = self
+            .world
+            .query::<&TimeOfDay>()
+            .iter()
+            .find_map(|(_, time)| time.lighting());
+        let lights: Vec<(Vec3, Light)> = self
+            .world
+            .query::<(&Position, &Light)>()
+            .iter()
+            .map(|(_, (position, light))| (position.0, *light))
+            .collect();
+        let mut q = self.world.query::<(
+            &ActiveCamera,
+            &mut ParallaxCamera,
+            Option<&Viewport>,
+            Option<&CameraShake>,
+            Option<&Atmosphere>,
+            Option<&Sky>,
+        )>();
+
+        for (entity, (_, cam, viewport, shake, atmosphere, sky)) in q.iter() {
+            let viewport = viewport.cloned().unwrap_or_default();
+            match self.target_size(&viewport.target) {
+                Some(size) => cam.resolution = viewport.resolution(size),
+                None => {
+                    log::warn!("camera renders to unknown target {:?}", viewport.target);
+                    continue;
+                }
+            }
 
-        let mut q = self.world.query::<(&ActiveCamera, &ParallaxCamera)>();
+            let cam = match shake {
+                Some(shake) => shake.apply(cam),
+                None => *cam,
+            };
+            let mut camera_uniform = cam.generate_matrix();
+            if let Some(atmosphere) = atmosphere {
+                atmosphere.apply(&mut camera_uniform);
+            }
+            apply_lights(&lights, cam.eye, &mut camera_uniform);
+            let mut sky = sky.cloned();
+            if let Some(lighting) = &lighting {
+                lighting.apply(&mut camera_uniform);
+                if let Some(sky) = &mut sky {
+                    lighting.apply_sky(sky);
+                }
+            }
+            frustums.push(Frustum::new(entity, cam, &camera_uniform));
 
-        let (_, (_, cam)) = q.iter().next().expect("No camera defined");
+            // Render textures go first so the screen sees this frame's contents.
+            let order = (viewport.target == RenderTarget::Screen, viewport.order);
+            views.push((
+                order,
+                View {
+                    camera_uniform,
+                    viewport: viewport.rect,
+                    target: viewport.target,
+                    sky,
+                    instances: HashMap::default(),
+                },
+            ));
+        }
+        drop(q);
+
+        // Each camera tests everything the spatial index can't rule out, so its stats are
+        // complete. Kept in entity order so sprites at the same depth draw in the same order
+        // every frame.
+        let mut visible: Vec<Vec<Entity>> = vec![];
+        {
+            let index = self
+                .world
+                .get::<SpatialIndex>(self.spatial_index)
+                .expect("spatial index exists");
+
+            for frustum in frustums.iter_mut() {
+                let candidates = index.query_visible(&frustum.camera);
+                frustum.stats.culled += (index.len() - candidates.len()) as u32;
+
+                let mut seen = vec![];
+                for entity in candidates {
+                    let mut q = match self.world.query_one::<(
+                        &Position,
+                        &Rotation,
+                        &Scale,
+                        &Sprite,
+                        Option<&SpriteSize>,
+                    )>(entity)
+                    {
+                        Ok(q) => q,
+                        Err(_) => continue,
+                    };
+                    if let Some((pos, rot, scale, sprite, sized)) = q.get() {
+                        let model = Instance {
+                            position: pos.0,
+                            rotation: rot.0,
+                            scale: scale.0 as f32,
+                            frame_id: sprite.frame_id,
+                            uv_offset: Vec2::zero(),
+                        }
+                        .model();
+                        let half = self
+                            .sprite_half_extents(sprite, sized)
+                            .expect("indexed sprites are loaded");
+                        if frustum.test(&model, half) {
+                            seen.push(entity);
+                        }
+                    }
+                }
+                visible.push(seen);
+            }
+        }
+
+        // Sized sprites need their own geometry, so they aren't instanced. Every view draws
+        // the ones any camera can see.
+        let mut seen_by_any: Vec<Entity> = visible.iter().flatten().copied().collect();
+        seen_by_any.sort_by_key(|entity| entity.id());
+        seen_by_any.dedup();
+        let mut sized_sprites = vec![];
+        for entity in seen_by_any {
+            let mut q = match self
+                .world
+                .query_one::<(&Position, &Scale, &Sprite, &SpriteSize)>(entity)
+            {
+                Ok(q) => q,
+                Err(_) => continue,
+            };
+            if let Some((pos, scale, sprite, size)) = q.get() {
+                sized_sprites.push(SizedSprite {
+                    sprite: sprite.id.clone(),
+                    frame_id: sprite.frame_id,
+                    position: pos.0,
+                    size: size.0 * scale.0 as f32,
+                    border_scale: scale.0 as f32,
+                });
+            }
+        }
+
+        // Instances are uploaded once per sprite, one run after another for each view, and
+        // each view draws only its own run.
+        let mut sprites: HashMap<String, Vec<InstanceRaw>> = HashMap::default();
+        for (((_, view), frustum), entities) in
+            views.iter_mut().zip(frustums.iter()).zip(visible.iter())
+        {
+            let mut instances: HashMap<String, Vec<InstanceRaw>> = HashMap::default();
+            for &entity in entities {
+                let mut q = match self.world.query_one::<(
+                    &Position,
+                    &Rotation,
+                    &Scale,
+                    &Sprite,
+                    Option<&UvScroll>,
+                    Option<&SpriteSize>,
+                )>(entity)
+                {
+                    Ok(q) => q,
+                    Err(_) => continue,
+                };
+                if let Some((pos, rot, scale, sprite, uv_scroll, None)) = q.get() {
+                    instances
+                        .entry(sprite.id.clone())
+                        .or_insert_with(Vec::new)
+                        .push(InstanceRaw::from(Instance {
+                            position: pos.0,
+                            rotation: rot.0,
+                            scale: scale.0 as f32,
+                            frame_id: sprite.frame_id,
+                            uv_offset: uv_scroll.map_or(Vec2::zero(), |scroll| scroll.offset),
+                        }));
+                }
+            }
+
+            // Repeating layers aren't in the spatial index, the camera generates the tiles it
+            // sees.
+            let mut q = self
+                .world
+                .query::<(&Position, &Scale, &RepeatingLayer, Option<&UvScroll>)>();
+            for (_, (pos, scale, layer, uv_scroll)) in q.iter() {
+                let size = match self.sprite_sizes.get(&layer.sprite) {
+                    Some(&size) => size,
+                    None => continue,
+                };
+                let half = half_extents(size) * scale.0 as f32;
+                let spacing = layer.spacing.unwrap_or(half * 2.0);
+                let anchor = Vec2::new(pos.0.x, pos.0.y);
+                let tiles = layer.visible_tiles(&frustum.camera, anchor, pos.0.z, spacing, half);
+
+                let instances = instances
+                    .entry(layer.sprite.clone())
+                    .or_insert_with(Vec::new);
+                for (column, row) in tiles {
+                    let offset = Vec3::new(column as f32 * spacing.x, row as f32 * spacing.y, 0.0);
+                    instances.push(InstanceRaw::from(Instance {
+                        position: pos.0 + offset,
+                        rotation: Quat::identity(),
+                        scale: scale.0 as f32,
+                        frame_id: layer.frame_id,
+                        uv_offset: uv_scroll.map_or(Vec2::zero(), |scroll| scroll.offset),
+                    }));
+                }
+            }
+            drop(q);
+
+            for (id, instances) in instances {
+                let uploaded = sprites.entry(id.clone()).or_insert_with(Vec::new);
+                let start = uploaded.len() as u32;
+                uploaded.extend(instances);
+                view.instances.insert(id, start..uploaded.len() as u32);
+            }
+        }
+
+        views.sort_by_key(|(order, _)| *order);
+
+        // Images go under text on the same layer.
+        let screen = self.screen_size();
+        let mut ui = vec![];
+        for (_, (node, image)) in self.world.query::<(&UiNode, &UiImage)>().iter() {
+            ui.push((
+                node.layer,
+                UiItem {
+                    rect: node.rect(screen),
+                    content: UiContent::Image(image.clone()),
+                },
+            ));
+        }
+
+        let mut texts = vec![];
+        let mut q = self
+            .world
+            .query::<(&Text, Option<&Position>, Option<&UiNode>)>();
+        for (_, (text, pos, node)) in q.iter() {
+            let (layer, rect) = match (text.space, pos, node) {
+                (_, _, Some(node)) => (node.layer, node.rect(screen)),
+                (TextSpace::Screen(position), _, None) => (0, Rect::new(position, position)),
+                (TextSpace::World, Some(pos), None) => {
+                    texts.push(TextItem {
+                        text: text.clone(),
+                        anchor: pos.0,
+                    });
+                    continue;
+                }
+                (TextSpace::World, None, None) => continue,
+            };
+            ui.push((
+                layer,
+                UiItem {
+                    rect,
+                    content: UiContent::Text(text.clone()),
+                },
+            ));
+        }
+        drop(q);
+        ui.sort_by_key(|(layer, _)| *layer);
+
+        let cameras: Vec<ParallaxCamera> = frustums.iter().map(|frustum| frustum.camera).collect();
+        let (debug_lines, debug_labels) = self.take_debug_shapes(&cameras);
+
+        // The top-down view stands in for every camera, taking the world space debug shapes
+        // drawn through them along.
+        let top_down = self
+            .world
+            .query::<&DebugDraw>()
+            .iter()
+            .any(|(_, debug)| debug.show_top_down);
+        if top_down {
+            views.clear();
+        }
+
+        for frustum in frustums {
+            if let Ok(mut stats) = self.world.get_mut::<CullStats>(frustum.entity) {
+                *stats = frustum.stats;
+            }
+        }
 
         Scene {
             sprite_instances: sprites,
-            camera_uniform: cam.generate_matrix(),
+            sized_sprites,
+            views: views.into_iter().map(|(_, view)| view).collect(),
+            asset_changes: std::mem::take(&mut self.asset_changes),
+            texts,
+            ui: ui.into_iter().map(|(_, item)| item).collect(),
+            debug_lines,
+            debug_labels,
         }
     }
+    // This frame's debug shapes, with the ones the toggles add for `cameras` underneath.
+    fn take_debug_shapes(&self, cameras: &[ParallaxCamera]) -> (Vec<DebugLine>, Vec<DebugLabel>) {
+        let mut q = self.world.query::<&mut DebugDraw>();
+        let debug = match q.iter().next() {
+            Some((_, debug)) => debug,
+            None => return (vec![], vec![]),
+        };
+        let drawn_lines = std::mem::take(&mut debug.lines);
+        let drawn_labels = std::mem::take(&mut debug.labels);
+
+        if debug.show_sprite_bounds || debug.show_pivots || debug.show_labels {
+            let mut q = self
+                .world
+                .query::<(&Position, &Rotation, &Scale, &Sprite, Option<&SpriteSize>)>();
+            for (entity, (pos, rot, scale, sprite, sized)) in q.iter() {
+                let space = DebugSpace::World(pos.0.z);
+                let centre = Vec2::new(pos.0.x, pos.0.y);
+                if debug.show_sprite_bounds {
+                    if let Some(half) = self.sprite_half_extents(sprite, sized) {
+                        let model = Instance {
+                            position: pos.0,
+                            rotation: rot.0,
+                            scale: scale.0 as f32,
+                            frame_id: sprite.frame_id,
+                            uv_offset: Vec2::zero(),
+                        }
+                        .model();
+                        let corners: Vec<Vec2> = QUAD_CORNERS
+                            .iter()
+                            .map(|&(x, y)| {
+                                let corner =
+                                    model.transform_point3(Vec3::new(x * half.x, y * half.y, 0.0));
+                                Vec2::new(corner.x, corner.y)
+                            })
+                            .collect();
+                        debug.polygon(space, &corners, GREEN);
+                    }
+                }
+                if debug.show_pivots {
+                    debug.cross(space, centre, 0.25, YELLOW);
+                }
+                if debug.show_labels {
+                    let label = format!("{} z{:.1}", entity.id(), pos.0.z);
+                    debug.text(space, centre, &label, YELLOW);
+                }
+            }
+        }
+
+        if debug.show_focal_plane {
+            // The grid lies in the x/y plane, which is only the focal plane for cameras looking
+            // down the z axis.
+            for cam in cameras.iter().filter(|cam| cam.looks_along_z()) {
+                let depth = (cam.eye + cam.look_dir.normalize() * cam.focal_distance).z;
+                let space = DebugSpace::World(depth);
+                let region = cam.visible_region(cam.focal_distance, Vec2::zero());
+
+                // Coarser when zoomed out, so the grid stays readable.
+                let mut step = 1.0;
+                while region.size().max_element() / step > 64.0 {
+                    step *= 2.0;
+                }
+                let colour = [RED[0], RED[1], RED[2], 0.4];
+                let mut x = (region.min.x / step).ceil() * step;
+                while x <= region.max.x {
+                    let from = Vec2::new(x, region.min.y);
+                    debug.line(space, from, Vec2::new(x, region.max.y), colour);
+                    x += step;
+                }
+                let mut y = (region.min.y / step).ceil() * step;
+                while y <= region.max.y {
+                    let from = Vec2::new(region.min.x, y);
+                    debug.line(space, from, Vec2::new(region.max.x, y), colour);
+                    y += step;
+                }
+            }
+        }
+
+        if debug.show_top_down {
+            if let Some(cam) = cameras.first() {
+                let sprites: Vec<(Vec3, f32)> = self
+                    .world
+                    .query::<(&Position, &Scale, &Sprite, Option<&SpriteSize>)>()
+                    .iter()
+                    .filter_map(|(_, (pos, scale, sprite, sized))| {
+                        let half = self.sprite_half_extents(sprite, sized)?;
+                        Some((pos.0, half.x * scale.0 as f32))
+                    })
+                    .collect();
+                let layers: Vec<f32> = self
+                    .world
+                    .query::<(&Position, &RepeatingLayer)>()
+                    .iter()
+                    .map(|(_, (pos, _))| pos.0.z)
+                    .collect();
+                draw_top_down(debug, cam, self.screen_size(), &sprites, &layers);
+            }
+        }
+
+        let mut lines = std::mem::take(&mut debug.lines);
+        let mut labels = std::mem::take(&mut debug.labels);
+        lines.extend(drawn_lines);
+        labels.extend(drawn_labels);
+        (lines, labels)
+    }
     fn capture_input(&self, event: winit::event::WindowEvent) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                let mut mouse = self
+                    .world
+                    .get_mut::<Mouse>(self.mouse)
+                    .expect("mouse exists");
+                mouse.position = Vec2::new(position.x as f32, position.y as f32);
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let mut mouse = self
+                    .world
+                    .get_mut::<Mouse>(self.mouse)
+                    .expect("mouse exists");
+                mouse.left_down = state == ElementState::Pressed;
+                match state {
+                    ElementState::Pressed => mouse.left_pressed = true,
+                    ElementState::Released => mouse.left_released = true,
+                }
+            }
+            _ => (),
+        }
+
         let mut q = self.world.query::<&mut KeyboardInput>();
         for (_, mut key) in q.iter() {
             // ignore non keyboard input
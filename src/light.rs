@@ -0,0 +1,120 @@
+use crate::gpu_primitives::CameraUniform;
+use glam::{Vec2, Vec3};
+
+/// The most lights that can light a camera's view. The ones closest to it are used.
+pub const MAX_LIGHTS: usize = 8;
+
+/// The ambient light sprites get once there are lights in the world, unless a
+/// [`crate::time_of_day::TimeOfDay`] sets it. Lights add to the ambient, so leaving it at full
+/// brightness would wash out everything they touch.
+pub const LIT_AMBIENT: f32 = 0.35;
+
+/// A light at its entity's [`crate::Position`], lighting the sprites near its depth and leaving
+/// layers further in front or behind as they are. Normal mapped sprites are shaded as if it
+/// hangs `height` in front of them.
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    /// Linear RGB.
+    pub colour: [f32; 3],
+    pub intensity: f32,
+    /// How far across it reaches, in metres.
+    pub radius: f32,
+    /// How far in front of or behind it, in metres, sprites are still lit, fading on the way.
+    pub depth_range: f32,
+    /// In metres. Lower lights graze normal mapped bumps, higher ones light them head on.
+    pub height: f32,
+    /// Makes it a spot light, a point light shines all the way round.
+    pub cone: Option<SpotCone>,
+}
+
+/// The part of the x/y plane a spot light shines on.
+#[derive(Clone, Copy, Debug)]
+pub struct SpotCone {
+    pub direction: Vec2,
+    /// Half the width of the cone, in radians.
+    pub angle: f32,
+    /// How much of the cone fades out towards its edges, from 0 for a hard edge to 1.
+    pub softness: f32,
+}
+
+impl Light {
+    pub fn point(colour: [f32; 3], intensity: f32, radius: f32) -> Self {
+        Light {
+            colour,
+            intensity,
+            radius,
+            depth_range: 5.0,
+            height: 1.0,
+            cone: None,
+        }
+    }
+    pub fn spot(colour: [f32; 3], intensity: f32, radius: f32, cone: SpotCone) -> Self {
+        Light {
+            cone: Some(cone),
+            ..Self::point(colour, intensity, radius)
+        }
+    }
+    pub fn with_depth_range(mut self, depth_range: f32) -> Self {
+        self.depth_range = depth_range;
+        self
+    }
+    pub fn with_height(mut self, height: f32) -> Self {
+        self.height = height;
+        self
+    }
+}
+
+impl SpotCone {
+    pub fn new(direction: Vec2, angle: f32) -> Self {
+        SpotCone {
+            direction,
+            angle,
+            softness: 0.2,
+        }
+    }
+    pub fn with_softness(mut self, softness: f32) -> Self {
+        self.softness = softness;
+        self
+    }
+}
+
+/// Gives the view from `eye` the [`MAX_LIGHTS`] of `lights` whose reach comes closest to it
+/// across the x/y plane, dimming the ambient to [`LIT_AMBIENT`] when there are any.
+pub(crate) fn apply_lights(lights: &[(Vec3, Light)], eye: Vec3, uniform: &mut CameraUniform) {
+    if !lights.is_empty() {
+        uniform.ambient = [LIT_AMBIENT, LIT_AMBIENT, LIT_AMBIENT, 1.0];
+    }
+    let gap = |(position, light): &(Vec3, Light)| {
+        let eye = Vec2::new(eye.x, eye.y);
+        (Vec2::new(position.x, position.y) - eye).length() - light.radius
+    };
+    let mut nearest: Vec<&(Vec3, Light)> = lights.iter().collect();
+    nearest.sort_by(|a, b| {
+        gap(*a)
+            .partial_cmp(&gap(*b))
+            .expect("light positions are numbers")
+    });
+
+    for (i, (position, light)) in nearest.into_iter().take(MAX_LIGHTS).enumerate() {
+        let [r, g, b] = light.colour;
+        uniform.light_positions[i] = [position.x, position.y, position.z, light.radius];
+        uniform.light_colours[i] = [
+            r * light.intensity,
+            g * light.intensity,
+            b * light.intensity,
+            0.0,
+        ];
+        // Point lights have no direction, which the shader takes as lighting all round.
+        uniform.light_spots[i] = match light.cone {
+            Some(cone) => {
+                let outer = cone.angle;
+                let inner = outer * (1.0 - cone.softness.max(0.0).min(1.0));
+                let direction = cone.direction.normalize();
+                [direction.x, direction.y, outer.cos(), inner.cos()]
+            }
+            None => [0.0; 4],
+        };
+        uniform.light_params[i] = [light.height, light.depth_range, 0.0, 0.0];
+        uniform.light_count[0] = i as u32 + 1;
+    }
+}
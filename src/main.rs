@@ -2,12 +2,31 @@
 #![feature(or_patterns)]
 extern crate parallax_scrolling_shader_demo;
 
-use glam::{Quat, Vec3};
+use glam::{Quat, Vec2, Vec3};
 use hecs::World;
 use parallax_scrolling_shader_demo::{
-    asset::SpriteAsset,
-    camera::{ActiveCamera, ParallaxCamera},
-    App, Game, KeyboardInput, Position, Rotation, Scale, Sprite,
+    asset::{FontAsset, Insets, SpriteAsset},
+    camera::{
+        atmosphere::{Atmosphere, ColourBand, Fog},
+        bounds::{confine_camera, CameraBounds},
+        dolly::{dolly_zoom, DollyAnchor, DollyZoom},
+        follow::{follow_camera, CameraFollow},
+        shake::{shake_camera, CameraShake},
+        sky::{Sky, SkyGradient},
+        timeline::{play_camera_timeline, CameraTimeline},
+        ActiveCamera, ParallaxCamera,
+    },
+    culling::CullStats,
+    debug::{DebugDraw, DebugSpace, RED},
+    layer::RepeatingLayer,
+    light::{Light, SpotCone},
+    scroll::{scroll_layers, ScrollVelocity},
+    streaming::{ChunkStreamer, WorldManifest},
+    text::{Text, TextAlign},
+    tiled::TiledMap,
+    time_of_day::{advance_time_of_day, Lighting, TimeOfDay},
+    ui::{Anchor, UiImage, UiInteraction, UiNode},
+    App, Game, KeyboardInput, Position, Rect, Rotation, Scale, Sprite, SpriteSize,
 };
 use std::time::{Duration, Instant};
 use winit::{
@@ -18,21 +37,46 @@ use winit::{
 #[derive(Clone, Copy)]
 struct MoveSpeed(f32);
 
+/// The keyboard input seen last frame, for toggling on new key presses only.
+struct LastKey(Option<winit::event::KeyboardInput>);
+
+/// Frames counted since the frame rate was last shown.
+struct FpsCounter {
+    frames: u32,
+    since: Duration,
+}
+
 fn main() {
     let event_loop = EventLoop::new();
     let app = futures::executor::block_on(App::new("parallax-scrolling-shader-demo", &event_loop));
     let mut parallax_demo = Game::new();
 
-    let sprite_assets = vec![
+    let level = TiledMap::load("assets/level.tmx").expect("level map is valid");
+
+    let mut sprite_assets = vec![
         SpriteAsset::new("player", vec!["assets/player.png"]),
-        SpriteAsset::new("apple", vec!["assets/apple.png"]),
-        SpriteAsset::new("ashberry", vec!["assets/ashberry.png"]),
-        SpriteAsset::new("baobab", vec!["assets/baobab.png"]),
-        SpriteAsset::new("beech", vec!["assets/beech.png"]),
+        SpriteAsset::new("treeline", vec!["assets/beech.png"]),
+        SpriteAsset::new("panel", vec!["assets/panel.png"]).with_nine_slice(Insets::uniform(3)),
     ];
+    sprite_assets.extend(level.sprite_assets());
 
     let movespeed = MoveSpeed(10.0);
 
+    let player = (
+        Position(Vec3::new(0.0, 0.0, 20.0)),
+        Rotation(Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.0)),
+        Scale(1),
+        KeyboardInput(None),
+        Sprite::new("player"),
+        movespeed,
+    );
+
+    let player = parallax_demo.spawn_entity(player);
+
+    let mut intro =
+        CameraTimeline::load("assets/intro.ron").expect("intro camera timeline is valid");
+    intro.play();
+
     let camera = (
         ParallaxCamera::new(
             Vec3::new(0.0, 3.0, 0.0),
@@ -40,58 +84,179 @@ fn main() {
             1.0,
             0.1,
             500.0,
-        ),
-        KeyboardInput(None),
+        )
+        .with_focal_distance(20.0),
         ActiveCamera,
-        movespeed,
-    );
-
-    let player = (
-        Position(Vec3::new(0.0, 0.0, 20.0)),
-        Rotation(Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.0)),
-        Scale(1),
+        CameraFollow::new(player)
+            .with_offset(Vec2::new(0.0, 3.0))
+            .with_dead_zone(Vec2::new(1.0, 0.5))
+            .with_look_ahead(0.1)
+            .with_smoothing(Vec2::new(0.25, 0.4))
+            .with_pixel_snap(true),
+        CameraBounds::new(Rect::new(Vec2::new(-20.0, -2.625), Vec2::new(20.0, 30.0)))
+            .with_track(player),
+        CameraShake::new(Vec2::new(0.5, 0.5), 0.05),
         KeyboardInput(None),
-        Sprite::new("player"),
-        movespeed,
+        intro,
+        DollyZoom::new(DollyAnchor::Entity(player), 1.0, 0.0),
+        CullStats::default(),
+        // Blue haze over the far layers, which are also paler.
+        Atmosphere::new()
+            .with_fog(Fog::linear([0.55, 0.65, 0.85, 0.7], 40.0, 450.0))
+            .with_band(
+                ColourBand::new(150.0, 500.0)
+                    .with_tint([0.85, 0.9, 1.0])
+                    .with_desaturation(0.4),
+            ),
+        // Fades into the haze at the horizon.
+        Sky::new([0.55, 0.65, 0.85, 1.0])
+            .with_gradient([0.2, 0.35, 0.7, 1.0], [0.55, 0.65, 0.85, 1.0]),
     );
 
-    let apple = (
-        Position(Vec3::new(-2.0, 0.0, 30.0)),
-        Rotation(Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.0)),
-        Scale(1),
-        Sprite::new("apple"),
-    );
+    parallax_demo.spawn_entity(camera);
 
-    let ashberry = (
-        Position(Vec3::new(2.0, 0.0, 30.0)),
-        Rotation(Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.0)),
-        Scale(1),
-        Sprite::new("ashberry"),
+    // A day passes every two minutes, starting mid morning.
+    let day = Lighting::new(
+        [1.0, 1.0, 1.0],
+        SkyGradient {
+            top: [0.2, 0.35, 0.7, 1.0],
+            bottom: [0.55, 0.65, 0.85, 1.0],
+        },
+        [0.55, 0.65, 0.85],
     );
-
-    let baobab = (
-        Position(Vec3::new(3.0, 0.0, 55.0)),
-        Rotation(Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.0)),
-        Scale(1),
-        Sprite::new("baobab"),
+    let dusk = Lighting::new(
+        [1.0, 0.7, 0.55],
+        SkyGradient {
+            top: [0.25, 0.2, 0.45, 1.0],
+            bottom: [0.95, 0.5, 0.3, 1.0],
+        },
+        [0.8, 0.5, 0.4],
     );
+    let night = Lighting::new(
+        [0.25, 0.3, 0.5],
+        SkyGradient {
+            top: [0.0, 0.0, 0.02, 1.0],
+            bottom: [0.03, 0.04, 0.1, 1.0],
+        },
+        [0.03, 0.04, 0.1],
+    );
+    parallax_demo.spawn_entity((TimeOfDay::new(10.0)
+        .with_speed(0.2)
+        .with_keyframe(0.0, night)
+        .with_keyframe(6.0, dusk)
+        .with_keyframe(8.0, day)
+        .with_keyframe(17.0, day)
+        .with_keyframe(19.0, dusk)
+        .with_keyframe(21.0, night),));
 
-    let beech = (
-        Position(Vec3::new(-3.5, 0.0, 95.0)),
-        Rotation(Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.0)),
-        Scale(1),
-        Sprite::new("beech"),
+    // A warm lamp between the player and the meadow, and a spot light over the orchard. They
+    // light the nearby layers and leave the distant hills to the sky.
+    parallax_demo.spawn_entity((
+        Position(Vec3::new(0.0, 1.5, 25.0)),
+        Light::point([1.0, 0.75, 0.45], 1.5, 6.0).with_depth_range(8.0),
+    ));
+    parallax_demo.spawn_entity((
+        Position(Vec3::new(15.5, 6.0, 30.0)),
+        Light::spot(
+            [0.6, 0.8, 1.0],
+            2.0,
+            9.0,
+            SpotCone::new(Vec2::new(0.0, -1.0), 0.45),
+        )
+        .with_height(3.0),
+    ));
+
+    // The ground, with its top edge under the player's feet.
+    level.spawn(&mut parallax_demo, Vec2::new(-20.0, -0.5));
+
+    // A line of trees on the horizon that goes on forever.
+    parallax_demo.spawn_entity((
+        Position(Vec3::new(0.0, 1.0, 250.0)),
+        Scale(2),
+        RepeatingLayer::new("treeline").with_spacing(Vec2::new(5.0, 0.0)),
+    ));
+
+    // Stand-in clouds drifting across the sky.
+    parallax_demo.spawn_entity((
+        Position(Vec3::new(0.0, 12.0, 400.0)),
+        Scale(3),
+        RepeatingLayer::new("treeline").with_spacing(Vec2::new(17.0, 0.0)),
+        ScrollVelocity(Vec2::new(-1.5, 0.0)),
+    ));
+
+    parallax_demo.load_font(FontAsset::bmfont("pixel", "assets/fonts/pixel.fnt"));
+
+    let mut sign = Text::new("pixel", "WELCOME ")
+        .with_align(TextAlign::Centre)
+        .with_scale(2.0);
+    sign.push_span("TRAVELLER!", [1.0, 0.8, 0.2, 1.0]);
+    parallax_demo.spawn_entity((Position(Vec3::new(-6.0, 2.0, 24.0)), sign));
+    // The board the sign is painted on, stretched to fit the text.
+    parallax_demo.spawn_entity((
+        Position(Vec3::new(-6.0, 1.72, 24.0)),
+        Rotation(Quat::identity()),
+        Scale(2),
+        Sprite::new("panel"),
+        SpriteSize(Vec2::new(4.0, 0.75)),
+    ));
+
+    parallax_demo.spawn_entity((
+        Text::new("pixel", "FPS:")
+            .on_screen(Vec2::new(8.0, 8.0))
+            .with_scale(3.0),
+        FpsCounter {
+            frames: 0,
+            since: Duration::from_secs(0),
+        },
+    ));
+
+    // A button in the top right corner that shakes the camera.
+    let button = UiNode::new(
+        Anchor::TopRight,
+        Vec2::new(-16.0, 16.0),
+        Vec2::new(200.0, 64.0),
     );
+    parallax_demo.spawn_entity((
+        button,
+        UiImage::new("panel").with_border_scale(4.0),
+        UiInteraction::default(),
+    ));
+    parallax_demo.spawn_entity((
+        UiNode::new(
+            Anchor::TopRight,
+            Vec2::new(-16.0, 28.0),
+            Vec2::new(200.0, 40.0),
+        )
+        .with_layer(1),
+        Text::new("pixel", "SHAKE")
+            .with_align(TextAlign::Centre)
+            .with_scale(3.0),
+    ));
 
-    parallax_demo.spawn_entity(player);
-    parallax_demo.spawn_entity(apple);
-    parallax_demo.spawn_entity(ashberry);
-    parallax_demo.spawn_entity(baobab);
-    parallax_demo.spawn_entity(beech);
-    parallax_demo.spawn_entity(camera);
+    // Hold F1 to outline sprites and show the focal plane, F2 switches to the top-down view.
+    parallax_demo.spawn_entity((
+        DebugDraw::new().with_font("pixel"),
+        KeyboardInput(None),
+        LastKey(None),
+    ));
+
+    let world = WorldManifest::load("assets/world.ron").expect("world manifest is valid");
+    parallax_demo.stream_world(ChunkStreamer::new(world));
 
     parallax_demo.add_system(&move_player);
-    parallax_demo.add_system(&move_camera);
+    parallax_demo.add_system(&scroll_layers);
+    parallax_demo.add_system(&advance_time_of_day);
+    parallax_demo.add_system(&follow_camera);
+    parallax_demo.add_system(&confine_camera);
+    parallax_demo.add_system(&play_camera_timeline);
+    parallax_demo.add_system(&dial_parallax);
+    parallax_demo.add_system(&dolly_zoom);
+    parallax_demo.add_system(&shake_on_space);
+    parallax_demo.add_system(&shake_on_click);
+    parallax_demo.add_system(&shake_camera);
+    parallax_demo.add_system(&count_fps);
+    parallax_demo.add_system(&show_debug_draw);
+    parallax_demo.add_system(&toggle_top_down);
 
     app.run(event_loop, parallax_demo, sprite_assets);
 }
@@ -102,6 +267,7 @@ fn move_player(world: &World, dt: Duration, _instant: Instant) {
     for (_, (key, pos, speed)) in q.iter() {
         if let Some(input) = key.0 {
             let dx = Vec3::new(speed.0 * dt.as_secs_f32(), 0.0, 0.0);
+            let dy = Vec3::new(0.0, speed.0 * dt.as_secs_f32(), 0.0);
             match input {
                 winit::event::KeyboardInput {
                     state: ElementState::Pressed,
@@ -117,39 +283,142 @@ fn move_player(world: &World, dt: Duration, _instant: Instant) {
                 } => {
                     pos.0 += dx;
                 }
+                winit::event::KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::Up),
+                    ..
+                } => {
+                    pos.0 += dy;
+                }
+                winit::event::KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::Down),
+                    ..
+                } => {
+                    pos.0 -= dy;
+                }
                 _ => (),
             }
         }
     }
 }
 
-fn move_camera(world: &World, dt: Duration, _instant: Instant) {
-    let mut q = world.query::<(
-        &ActiveCamera,
-        &mut ParallaxCamera,
-        &KeyboardInput,
-        &MoveSpeed,
-    )>();
-
-    let (_, (_, cam, key, speed)) = q.iter().next().expect("active camera is preset");
-    if let Some(input) = key.0 {
-        let dx = Vec3::new(speed.0 * dt.as_secs_f32(), 0.0, 0.0);
-        match input {
-            winit::event::KeyboardInput {
-                state: ElementState::Pressed,
-                virtual_keycode: Some(VirtualKeyCode::Left),
-                ..
-            } => {
-                cam.eye -= dx;
+fn shake_on_space(world: &World, _dt: Duration, _instant: Instant) {
+    let mut q = world.query::<(&KeyboardInput, &mut CameraShake)>();
+
+    for (_, (key, shake)) in q.iter() {
+        if let Some(winit::event::KeyboardInput {
+            state: ElementState::Pressed,
+            virtual_keycode: Some(VirtualKeyCode::Space),
+            ..
+        }) = key.0
+        {
+            shake.add_trauma(0.5);
+        }
+    }
+}
+
+fn shake_on_click(world: &World, _dt: Duration, _instant: Instant) {
+    let mut clicked = false;
+    for (_, (interaction, image)) in world.query::<(&UiInteraction, &mut UiImage)>().iter() {
+        clicked |= interaction.clicked;
+        image.colour = match (interaction.pressed, interaction.hovered) {
+            (true, _) => [0.7, 0.7, 0.7, 1.0],
+            (false, true) => [1.2, 1.2, 1.2, 1.0],
+            (false, false) => [1.0, 1.0, 1.0, 1.0],
+        };
+    }
+
+    if clicked {
+        for (_, shake) in world.query::<&mut CameraShake>().iter() {
+            shake.add_trauma(0.5);
+        }
+    }
+}
+
+fn dial_parallax(world: &World, _dt: Duration, _instant: Instant) {
+    let mut q = world.query::<(&KeyboardInput, &mut DollyZoom)>();
+
+    for (_, (key, dolly)) in q.iter() {
+        if let Some(winit::event::KeyboardInput {
+            state: ElementState::Pressed,
+            virtual_keycode: Some(keycode),
+            ..
+        }) = key.0
+        {
+            let fov = match keycode {
+                VirtualKeyCode::Key1 => 0.6,
+                VirtualKeyCode::Key2 => 1.0,
+                VirtualKeyCode::Key3 => 1.6,
+                _ => continue,
+            };
+            // The key stays reported until the next event, don't restart every frame.
+            if (dolly.to_fov - fov).abs() > f32::EPSILON {
+                dolly.retarget(fov, 1.5);
+            }
+        }
+    }
+}
+
+fn count_fps(world: &World, dt: Duration, _instant: Instant) {
+    let mut q = world.query::<(&mut Text, &mut FpsCounter)>();
+
+    for (_, (text, counter)) in q.iter() {
+        counter.frames += 1;
+        counter.since += dt;
+        if counter.since >= Duration::from_millis(500) {
+            let fps = counter.frames as f32 / counter.since.as_secs_f32();
+            text.set_text(&format!("FPS: {:.0}", fps));
+            counter.frames = 0;
+            counter.since = Duration::from_secs(0);
+        }
+    }
+}
+
+fn show_debug_draw(world: &World, _dt: Duration, _instant: Instant) {
+    let mut q = world.query::<(&KeyboardInput, &mut DebugDraw)>();
+
+    for (_, (key, debug)) in q.iter() {
+        if let Some(winit::event::KeyboardInput {
+            state,
+            virtual_keycode: Some(VirtualKeyCode::F1),
+            ..
+        }) = key.0
+        {
+            let show = state == ElementState::Pressed;
+            debug.show_sprite_bounds = show;
+            debug.show_pivots = show;
+            debug.show_labels = show;
+            debug.show_focal_plane = show;
+        }
+
+        // The regions the camera is confined to, on its focal plane.
+        if debug.show_sprite_bounds {
+            for (_, (bounds, cam)) in world.query::<(&CameraBounds, &ParallaxCamera)>().iter() {
+                let space = DebugSpace::World(cam.eye.z + cam.focal_distance);
+                for region in bounds.regions.iter() {
+                    debug.rect(space, *region, RED);
+                }
             }
-            winit::event::KeyboardInput {
+        }
+    }
+}
+
+fn toggle_top_down(world: &World, _dt: Duration, _instant: Instant) {
+    let mut q = world.query::<(&KeyboardInput, &mut LastKey, &mut DebugDraw)>();
+
+    for (_, (key, last, debug)) in q.iter() {
+        // The key stays reported until the next event, only toggle on a new one.
+        if key.0 != last.0 {
+            if let Some(winit::event::KeyboardInput {
                 state: ElementState::Pressed,
-                virtual_keycode: Some(VirtualKeyCode::Right),
+                virtual_keycode: Some(VirtualKeyCode::F2),
                 ..
-            } => {
-                cam.eye += dx;
+            }) = key.0
+            {
+                debug.show_top_down = !debug.show_top_down;
             }
-            _ => (),
         }
+        last.0 = key.0;
     }
 }
@@ -0,0 +1,314 @@
+use crate::{
+    font::Font,
+    gpu_primitives::{Index, QuadVertex},
+    sprite::Sprite,
+};
+use glam::{Vec2, Vec3};
+use std::{mem, ops::Range};
+
+// Quads a batch has room for until a frame needs more.
+const INITIAL_QUADS: u64 = 256;
+
+/// The textures a quad samples, by index into the renderer's fonts or sprites.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Binding {
+    Font(usize),
+    Sprite(usize),
+    /// Plain white, for solid colours.
+    Blank,
+}
+
+/// Where quads built in pixels end up, y down from the quad's origin.
+#[derive(Clone, Copy, Debug)]
+pub enum Placement {
+    /// Hanging from a world position, at this many metres per pixel.
+    World { anchor: Vec3, metres: f32 },
+    /// On a screen of this size, at this position and screen pixels per pixel.
+    Screen {
+        position: Vec2,
+        scale: f32,
+        size: (u32, u32),
+    },
+}
+
+impl Placement {
+    fn offset(&self, x: f32, y: f32) -> [f32; 4] {
+        match *self {
+            Placement::World { metres, .. } => [x * metres, -y * metres, 0.0, 0.0],
+            Placement::Screen {
+                position,
+                scale,
+                size: (width, height),
+            } => {
+                let x = position.x + x * scale;
+                let y = position.y + y * scale;
+                [
+                    x / width as f32 * 2.0 - 1.0,
+                    1.0 - y / height as f32 * 2.0,
+                    0.0,
+                    1.0,
+                ]
+            }
+        }
+    }
+    fn anchor(&self) -> [f32; 4] {
+        match *self {
+            Placement::World { anchor, .. } => [anchor.x, anchor.y, anchor.z, 1.0],
+            Placement::Screen { .. } => [0.0; 4],
+        }
+    }
+}
+
+/// Collects quads in draw order, batching runs that sample the same textures.
+#[derive(Default)]
+pub struct QuadBatchBuilder {
+    vertices: Vec<QuadVertex>,
+    indices: Vec<Index>,
+    draws: Vec<(Binding, Range<u32>)>,
+}
+
+impl QuadBatchBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a quad covering `rect` in pixels (min x, min y, max x, max y) and sampling `uv`
+    /// (same order) from `page` of `binding`.
+    pub fn push(
+        &mut self,
+        binding: Binding,
+        placement: &Placement,
+        rect: [f32; 4],
+        uv: [f32; 4],
+        page: u32,
+        colour: [f32; 4],
+    ) {
+        let [x0, y0, x1, y1] = rect;
+        let [u0, v0, u1, v1] = uv;
+        if x1 <= x0 || y1 <= y0 {
+            return;
+        }
+
+        let corners = [
+            (x0, y1, u0, v1),
+            (x1, y1, u1, v1),
+            (x1, y0, u1, v0),
+            (x0, y0, u0, v0),
+        ];
+        self.push_corners(binding, placement, corners, page, colour);
+    }
+    /// Adds a solid line `width` pixels wide.
+    pub fn push_line(
+        &mut self,
+        placement: &Placement,
+        from: Vec2,
+        to: Vec2,
+        width: f32,
+        colour: [f32; 4],
+    ) {
+        let along = to - from;
+        if along.length_squared() <= 0.0 {
+            return;
+        }
+        let side = Vec2::new(-along.y, along.x).normalize() * width / 2.0;
+        let corners = [from - side, to - side, to + side, from + side];
+        let corners = [
+            (corners[0].x, corners[0].y, 0.0, 0.0),
+            (corners[1].x, corners[1].y, 0.0, 0.0),
+            (corners[2].x, corners[2].y, 0.0, 0.0),
+            (corners[3].x, corners[3].y, 0.0, 0.0),
+        ];
+        self.push_corners(Binding::Blank, placement, corners, 0, colour);
+    }
+    /// Adds a quad with corners `(x, y, u, v)` in pixels, going round from the bottom left.
+    pub fn push_corners(
+        &mut self,
+        binding: Binding,
+        placement: &Placement,
+        corners: [(f32, f32, f32, f32); 4],
+        page: u32,
+        colour: [f32; 4],
+    ) {
+        self.push_shaded(binding, placement, corners, page, [colour; 4]);
+    }
+    /// Adds a solid quad over `rect`, blending from `top` to `bottom`.
+    pub fn push_gradient(
+        &mut self,
+        placement: &Placement,
+        rect: [f32; 4],
+        top: [f32; 4],
+        bottom: [f32; 4],
+    ) {
+        let [x0, y0, x1, y1] = rect;
+        if x1 <= x0 || y1 <= y0 {
+            return;
+        }
+
+        let corners = [
+            (x0, y1, 0.0, 0.0),
+            (x1, y1, 0.0, 0.0),
+            (x1, y0, 0.0, 0.0),
+            (x0, y0, 0.0, 0.0),
+        ];
+        let colours = [bottom, bottom, top, top];
+        self.push_shaded(Binding::Blank, placement, corners, 0, colours);
+    }
+    // Like `push_corners` with a colour for each corner.
+    fn push_shaded(
+        &mut self,
+        binding: Binding,
+        placement: &Placement,
+        corners: [(f32, f32, f32, f32); 4],
+        page: u32,
+        colours: [[f32; 4]; 4],
+    ) {
+        let first = self.vertices.len() as Index;
+        let anchor = placement.anchor();
+        for (&(x, y, u, v), &colour) in corners.iter().zip(colours.iter()) {
+            self.vertices.push(QuadVertex {
+                offset: placement.offset(x, y),
+                tex_coord: [u, v],
+                anchor,
+                colour,
+                page,
+            });
+        }
+
+        let start = self.indices.len() as u32;
+        self.indices
+            .extend([0, 1, 2, 2, 3, 0].iter().map(|offset| first + offset));
+        let end = self.indices.len() as u32;
+
+        match self.draws.last_mut() {
+            Some((last, indices)) if *last == binding => indices.end = end,
+            _ => self.draws.push((binding, start..end)),
+        }
+    }
+    /// Replaces the quads `batch` draws with these, growing its buffers if they don't fit.
+    pub fn build(self, device: &wgpu::Device, queue: &wgpu::Queue, batch: &mut QuadBatch) {
+        batch.draws = self.draws;
+        if batch.draws.is_empty() {
+            return;
+        }
+
+        let vertices = self.vertices.len() as u64;
+        if vertices > batch.vertex_capacity {
+            batch.vertex_capacity = vertices.next_power_of_two();
+            batch.vertex_buffer = create_buffer::<QuadVertex>(
+                device,
+                "Quad Vertex Buffer",
+                batch.vertex_capacity,
+                wgpu::BufferUsage::VERTEX,
+            );
+        }
+        let indices = self.indices.len() as u64;
+        if indices > batch.index_capacity {
+            batch.index_capacity = indices.next_power_of_two();
+            batch.index_buffer = create_buffer::<Index>(
+                device,
+                "Quad Index Buffer",
+                batch.index_capacity,
+                wgpu::BufferUsage::INDEX,
+            );
+        }
+
+        queue.write_buffer(
+            &batch.vertex_buffer,
+            0,
+            bytemuck::cast_slice(&self.vertices),
+        );
+        queue.write_buffer(&batch.index_buffer, 0, bytemuck::cast_slice(&self.indices));
+    }
+}
+
+/// Glyph and UI quads, drawn in the order they were added. Its buffers are kept from frame to
+/// frame, so build into the same batch every frame.
+pub struct QuadBatch {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    vertex_capacity: u64,
+    index_capacity: u64,
+    draws: Vec<(Binding, Range<u32>)>,
+}
+
+impl QuadBatch {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let vertex_capacity = INITIAL_QUADS * 4;
+        let index_capacity = INITIAL_QUADS * 6;
+        QuadBatch {
+            vertex_buffer: create_buffer::<QuadVertex>(
+                device,
+                "Quad Vertex Buffer",
+                vertex_capacity,
+                wgpu::BufferUsage::VERTEX,
+            ),
+            index_buffer: create_buffer::<Index>(
+                device,
+                "Quad Index Buffer",
+                index_capacity,
+                wgpu::BufferUsage::INDEX,
+            ),
+            vertex_capacity,
+            index_capacity,
+            draws: vec![],
+        }
+    }
+    /// Whether the last build added no quads.
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+}
+
+fn create_buffer<T>(
+    device: &wgpu::Device,
+    label: &str,
+    capacity: u64,
+    usage: wgpu::BufferUsage,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        usage: usage | wgpu::BufferUsage::COPY_DST,
+        size: capacity * mem::size_of::<T>() as u64,
+        mapped_at_creation: false,
+    })
+}
+
+pub trait DrawQuads<'a, 'b>
+where
+    'b: 'a,
+{
+    fn draw_quads(
+        &mut self,
+        batch: &'b QuadBatch,
+        fonts: &'b [Font],
+        sprites: &'b [Sprite],
+        blank: &'b wgpu::BindGroup,
+        uniform_bind_group: &'b wgpu::BindGroup,
+    );
+}
+
+impl<'a, 'b> DrawQuads<'a, 'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_quads(
+        &mut self,
+        batch: &'b QuadBatch,
+        fonts: &'b [Font],
+        sprites: &'b [Sprite],
+        blank: &'b wgpu::BindGroup,
+        uniform_bind_group: &'b wgpu::BindGroup,
+    ) {
+        self.set_vertex_buffer(0, batch.vertex_buffer.slice(..));
+        self.set_index_buffer(batch.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_bind_group(0, uniform_bind_group, &[]);
+        for (binding, indices) in batch.draws.iter() {
+            let bind_group = match *binding {
+                Binding::Font(i) => &fonts[i].bind_group,
+                Binding::Sprite(i) => &sprites[i].bind_group,
+                Binding::Blank => blank,
+            };
+            self.set_bind_group(1, bind_group, &[]);
+            self.draw_indexed(indices.clone(), 0, 0..1);
+        }
+    }
+}
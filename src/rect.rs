@@ -0,0 +1,58 @@
+use glam::Vec2;
+use serde::Deserialize;
+
+/// An axis aligned rectangle in the x/y plane.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct Rect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Rect {
+    pub fn new(min: Vec2, max: Vec2) -> Self {
+        Rect { min, max }
+    }
+    pub fn from_centre(centre: Vec2, half_extents: Vec2) -> Self {
+        Rect {
+            min: centre - half_extents,
+            max: centre + half_extents,
+        }
+    }
+    pub fn centre(&self) -> Vec2 {
+        (self.min + self.max) / 2.0
+    }
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+    }
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+    }
+    /// The smallest rectangle containing both.
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+    /// The rectangle grown by `margin` on every side.
+    pub fn expand(&self, margin: f32) -> Rect {
+        Rect {
+            min: self.min - Vec2::splat(margin),
+            max: self.max + Vec2::splat(margin),
+        }
+    }
+    /// Distance from `point` to the closest point of the rectangle, zero if it is inside.
+    pub fn distance_to(&self, point: Vec2) -> f32 {
+        let closest = point.max(self.min).min(self.max);
+        (point - closest).length()
+    }
+}
@@ -1,21 +1,82 @@
 use crate::{
-    asset::SpriteAsset,
-    gpu_primitives::{CameraUniform, InstanceRaw, Vertex},
-    scene::Scene,
-    sprite::{DrawSprite, Sprite},
-    texture::DepthTexture,
+    asset::{FontAsset, SpriteAsset},
+    camera::{sky::Sky, RenderTarget},
+    debug::{DebugLabel, DebugLine, DebugSpace},
+    font::Font,
+    gpu_primitives::{CameraUniform, InstanceRaw, QuadVertex, Vertex},
+    quad::{Binding, DrawQuads, Placement, QuadBatch, QuadBatchBuilder},
+    scene::{AssetChange, Scene, UiContent, UiItem},
+    sprite::{
+        nine_slice, texture_array_bind_group, DrawSprite, LightingMaps, Sprite, PIXELS_PER_METRE,
+    },
+    text::{TextAlign, WHITE},
+    texture::{ArrayTexture, DepthTexture, RenderTexture},
 };
-use std::{mem, num::NonZeroU32};
+use glam::{Vec2, Vec3};
+use image::RgbaImage;
+use std::{collections::HashMap, mem, num::NonZeroU32};
 use wgpu::{util::DeviceExt, BlendFactor, BlendOperation};
 
 pub const TEXTURE_ARRAY_SIZE: u32 = 128;
 
+const CLEAR_COLOUR: wgpu::Color = wgpu::Color {
+    r: 0.0,
+    g: 0.0,
+    b: 0.0,
+    a: 0.0,
+};
+
 pub struct Renderer {
     sprites: Vec<Sprite>,
-    uniform_buffer: wgpu::Buffer,
+    render_textures: HashMap<String, RenderTexture>,
+    fonts: Vec<Font>,
     pipeline: wgpu::RenderPipeline,
+    quad_pipeline: wgpu::RenderPipeline,
+    debug_pipeline: wgpu::RenderPipeline,
+    // Sampled by solid colour quads.
+    _blank: ArrayTexture,
+    blank_bind_group: wgpu::BindGroup,
     depth_texture: DepthTexture,
-    uniform_bind_group: wgpu::BindGroup,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
+    sprite_bind_group_layout: wgpu::BindGroupLayout,
+    lighting_maps: LightingMaps,
+    format: wgpu::TextureFormat,
+    // One per view, grown as cameras are added. Each view needs its own buffer because every
+    // write lands before the command buffer runs.
+    view_uniforms: Vec<ViewUniform>,
+    // Each view's sky, for the same reason.
+    view_skies: Vec<QuadBatch>,
+    world_quads: QuadBatch,
+    ui: QuadBatch,
+    world_debug: QuadBatch,
+    // Bound for the UI overlay, which doesn't use the camera.
+    overlay_uniform: ViewUniform,
+}
+
+struct ViewUniform {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ViewUniform {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Uniform Buffer"),
+            contents: &[0u8; mem::size_of::<CameraUniform>()],
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        ViewUniform { buffer, bind_group }
+    }
 }
 
 impl Renderer {
@@ -25,18 +86,12 @@ impl Renderer {
         queue: &wgpu::Queue,
         sprite_assets: Vec<SpriteAsset>,
     ) -> Self {
-        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform Buffer"),
-            contents: &[0u8; mem::size_of::<CameraUniform>()],
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-        });
-
         let uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         min_binding_size: wgpu::BufferSize::new(
@@ -48,15 +103,6 @@ impl Renderer {
                 }],
             });
 
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-        });
-
         let sprite_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
@@ -86,13 +132,18 @@ impl Renderer {
                 ],
             });
 
-        let mut sprites = vec![];
-
-        for asset in sprite_assets {
-            sprites.push(Sprite::new(device, queue, &sprite_bind_group_layout, asset));
-        }
+        let lighting_maps = LightingMaps::new(device, queue);
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                &uniform_bind_group_layout,
+                &sprite_bind_group_layout,
+                &lighting_maps.layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let quad_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
             bind_group_layouts: &[&uniform_bind_group_layout, &sprite_bind_group_layout],
             push_constant_ranges: &[],
@@ -104,7 +155,7 @@ impl Renderer {
         let fs_module =
             device.create_shader_module(&wgpu::include_spirv!("../shaders/shader.frag.spv"));
 
-        let depth_texture = DepthTexture::new(&device, &sc_desc);
+        let depth_texture = DepthTexture::new(&device, sc_desc.width, sc_desc.height);
 
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
@@ -147,55 +198,422 @@ impl Renderer {
             multisample: wgpu::MultisampleState::default(),
         });
 
-        Renderer {
-            uniform_buffer,
+        // Glyphs and world space quads are tested against sprites but don't hide each other's
+        // soft edges. Debug shapes go over everything.
+        let quad_pipeline = create_quad_pipeline(
+            device,
+            &quad_pipeline_layout,
+            sc_desc.format,
+            wgpu::CompareFunction::Less,
+        );
+        let debug_pipeline = create_quad_pipeline(
+            device,
+            &quad_pipeline_layout,
+            sc_desc.format,
+            wgpu::CompareFunction::Always,
+        );
+
+        let blank = ArrayTexture::new(
+            device,
+            queue,
+            RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255])),
+        );
+        let blank_bind_group =
+            texture_array_bind_group(device, &sprite_bind_group_layout, &[&blank.view]);
+
+        let overlay_uniform = ViewUniform::new(device, &uniform_bind_group_layout);
+
+        let mut renderer = Renderer {
             pipeline,
-            sprites,
+            quad_pipeline,
+            debug_pipeline,
+            _blank: blank,
+            blank_bind_group,
+            fonts: vec![],
+            sprites: vec![],
+            render_textures: HashMap::new(),
             depth_texture,
-            uniform_bind_group,
+            uniform_bind_group_layout,
+            sprite_bind_group_layout,
+            lighting_maps,
+            format: sc_desc.format,
+            view_uniforms: vec![],
+            view_skies: vec![],
+            world_quads: QuadBatch::new(device),
+            ui: QuadBatch::new(device),
+            world_debug: QuadBatch::new(device),
+            overlay_uniform,
+        };
+        for asset in sprite_assets {
+            renderer.load_sprite(device, queue, asset);
+        }
+        renderer
+    }
+
+    /// Matches the depth buffer to a swap chain rebuilt for a new window size.
+    pub fn resize(&mut self, device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) {
+        self.depth_texture = DepthTexture::new(device, sc_desc.width, sc_desc.height);
+    }
+
+    fn load_sprite(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, asset: SpriteAsset) {
+        // Reloading replaces the old sprite.
+        self.unload_sprite(&asset.id);
+        match asset.render_target {
+            Some((width, height)) => {
+                let texture = RenderTexture::new(device, self.format, width, height);
+                self.sprites.push(Sprite::from_render_texture(
+                    device,
+                    &self.sprite_bind_group_layout,
+                    &self.lighting_maps,
+                    &asset.id,
+                    &texture,
+                ));
+                self.render_textures.insert(asset.id, texture);
+            }
+            None => {
+                let id = asset.id.clone();
+                match Sprite::new(
+                    device,
+                    queue,
+                    &self.sprite_bind_group_layout,
+                    &self.lighting_maps,
+                    asset,
+                ) {
+                    Ok(sprite) => self.sprites.push(sprite),
+                    Err(err) => log::error!("could not load sprite {}: {}", id, err),
+                }
+            }
+        }
+    }
+
+    fn unload_sprite(&mut self, id: &str) {
+        self.sprites.retain(|sprite| sprite.id != id);
+        self.render_textures.remove(id);
+    }
+
+    fn load_font(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, asset: FontAsset) {
+        match Font::new(device, queue, &self.sprite_bind_group_layout, &asset) {
+            Ok(font) => {
+                self.fonts.retain(|loaded| loaded.id != font.id);
+                self.fonts.push(font);
+            }
+            Err(err) => log::error!("could not load font {}: {}", asset.id, err),
+        }
+    }
+
+    // Stretches a frame of a sprite over `bounds`, in pixels of `placement`, keeping nine-slice
+    // borders at `border_scale` pixels per image pixel.
+    #[allow(clippy::too_many_arguments)]
+    fn push_sprite(
+        &self,
+        batch: &mut QuadBatchBuilder,
+        id: &str,
+        placement: &Placement,
+        bounds: [f32; 4],
+        frame_id: u32,
+        colour: [f32; 4],
+        border_scale: f32,
+    ) {
+        let i = match self.sprites.iter().position(|sprite| sprite.id == id) {
+            Some(i) => i,
+            None => return,
+        };
+        let sprite = &self.sprites[i];
+        let patches = match &sprite.nine_slice {
+            Some(insets) => nine_slice(bounds, sprite.size, insets, border_scale),
+            None => vec![(bounds, [0.0, 0.0, 1.0, 1.0])],
+        };
+        for (bounds, uv) in patches {
+            batch.push(Binding::Sprite(i), placement, bounds, uv, frame_id, colour);
+        }
+    }
+
+    /// Fills a viewport `size` pixels big with `sky`, starting with a quad of its clear colour
+    /// if the target was already cleared by an earlier view.
+    fn push_sky(
+        &self,
+        batch: &mut QuadBatchBuilder,
+        sky: &Sky,
+        target: &RenderTarget,
+        fill: bool,
+        size: (f32, f32),
+    ) {
+        let (width, height) = size;
+        let placement = Placement::Screen {
+            position: Vec2::zero(),
+            scale: 1.0,
+            size: (width.round() as u32, height.round() as u32),
+        };
+        let rect = [0.0, 0.0, width.round(), height.round()];
+
+        if fill {
+            batch.push_gradient(&placement, rect, sky.clear_colour, sky.clear_colour);
+        }
+        if let Some(gradient) = &sky.gradient {
+            batch.push_gradient(&placement, rect, gradient.top, gradient.bottom);
+        }
+
+        for backdrop in sky.backdrops.iter() {
+            // A texture can't be sampled while it is being rendered to.
+            if matches!(target, RenderTarget::Texture(id) if *id == backdrop.sprite) {
+                continue;
+            }
+            let i = match self.sprites.iter().position(|s| s.id == backdrop.sprite) {
+                Some(i) => i,
+                None => continue,
+            };
+
+            // Scale the frame to cover the viewport and crop the middle of it.
+            let (frame_width, frame_height) = self.sprites[i].size;
+            let scale = (width / frame_width as f32).max(height / frame_height as f32);
+            let u = width / (frame_width as f32 * scale);
+            let v = height / (frame_height as f32 * scale);
+            let uv = [
+                (1.0 - u) / 2.0,
+                (1.0 - v) / 2.0,
+                (1.0 + u) / 2.0,
+                (1.0 + v) / 2.0,
+            ];
+            batch.push(
+                Binding::Sprite(i),
+                &placement,
+                rect,
+                uv,
+                backdrop.frame_id,
+                backdrop.colour,
+            );
+        }
+    }
+
+    fn push_ui_item(&self, batch: &mut QuadBatchBuilder, item: &UiItem, screen_size: (u32, u32)) {
+        let rect = &item.rect;
+        match &item.content {
+            UiContent::Image(image) => {
+                let placement = Placement::Screen {
+                    position: Vec2::zero(),
+                    scale: 1.0,
+                    size: screen_size,
+                };
+                self.push_sprite(
+                    batch,
+                    &image.sprite,
+                    &placement,
+                    [rect.min.x, rect.min.y, rect.max.x, rect.max.y],
+                    image.frame_id,
+                    image.colour,
+                    image.border_scale,
+                );
+            }
+            UiContent::Text(text) => {
+                let i = match self.fonts.iter().position(|font| font.id == text.font) {
+                    Some(i) => i,
+                    None => return,
+                };
+                let x = match text.align {
+                    TextAlign::Left => rect.min.x,
+                    TextAlign::Centre => rect.centre().x,
+                    TextAlign::Right => rect.max.x,
+                };
+                let placement = Placement::Screen {
+                    position: Vec2::new(x, rect.min.y),
+                    scale: text.scale,
+                    size: screen_size,
+                };
+                self.fonts[i].push_text(batch, Binding::Font(i), text, &placement);
+            }
         }
     }
 
+    fn push_debug_label(
+        &self,
+        batch: &mut QuadBatchBuilder,
+        label: &DebugLabel,
+        screen_size: (u32, u32),
+    ) {
+        let i = match self
+            .fonts
+            .iter()
+            .position(|font| font.id == label.text.font)
+        {
+            Some(i) => i,
+            None => return,
+        };
+        let placement = match label.space {
+            DebugSpace::World(depth) => Placement::World {
+                anchor: Vec3::new(label.position.x, label.position.y, depth),
+                metres: label.text.scale / PIXELS_PER_METRE as f32,
+            },
+            DebugSpace::Screen => Placement::Screen {
+                position: label.position,
+                scale: label.text.scale,
+                size: screen_size,
+            },
+        };
+        self.fonts[i].push_text(batch, Binding::Font(i), &label.text, &placement);
+    }
+
     pub fn render(
         &mut self,
         frame: &wgpu::SwapChainTexture,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        _sc_desc: &wgpu::SwapChainDescriptor,
+        sc_desc: &wgpu::SwapChainDescriptor,
         scene: Scene,
     ) {
-        queue.write_buffer(
-            &self.uniform_buffer,
-            0,
-            bytemuck::bytes_of(&scene.camera_uniform),
-        );
+        for change in scene.asset_changes {
+            match change {
+                AssetChange::Load(asset) => self.load_sprite(device, queue, asset),
+                AssetChange::Unload(id) => self.unload_sprite(&id),
+                AssetChange::LoadFont(asset) => self.load_font(device, queue, asset),
+            }
+        }
+
+        // Sized sprites first, so world space text can go on them.
+        let mut world_quads = QuadBatchBuilder::new();
+        for sized in scene.sized_sprites.iter() {
+            let placement = Placement::World {
+                anchor: sized.position,
+                metres: 1.0 / PIXELS_PER_METRE as f32,
+            };
+            let half = sized.size * PIXELS_PER_METRE as f32 / 2.0;
+            self.push_sprite(
+                &mut world_quads,
+                &sized.sprite,
+                &placement,
+                [-half.x, -half.y, half.x, half.y],
+                sized.frame_id,
+                WHITE,
+                sized.border_scale,
+            );
+        }
+        for item in scene.texts.iter() {
+            if let Some(i) = self.fonts.iter().position(|font| font.id == item.text.font) {
+                let placement = Placement::World {
+                    anchor: item.anchor,
+                    metres: item.text.scale / PIXELS_PER_METRE as f32,
+                };
+                self.fonts[i].push_text(&mut world_quads, Binding::Font(i), &item.text, &placement);
+            }
+        }
+        world_quads.build(device, queue, &mut self.world_quads);
+
+        let screen_size = (sc_desc.width, sc_desc.height);
+        let mut ui = QuadBatchBuilder::new();
+        for item in scene.ui.iter() {
+            self.push_ui_item(&mut ui, item, screen_size);
+        }
+        for line in scene
+            .debug_lines
+            .iter()
+            .filter(|line| line.space == DebugSpace::Screen)
+        {
+            push_debug_line(&mut ui, line, screen_size);
+        }
+        for label in scene.debug_labels.iter() {
+            if label.space == DebugSpace::Screen {
+                self.push_debug_label(&mut ui, label, screen_size);
+            }
+        }
+        ui.build(device, queue, &mut self.ui);
+
+        let mut world_debug = QuadBatchBuilder::new();
+        for line in scene
+            .debug_lines
+            .iter()
+            .filter(|line| line.space != DebugSpace::Screen)
+        {
+            push_debug_line(&mut world_debug, line, screen_size);
+        }
+        for label in scene.debug_labels.iter() {
+            if label.space != DebugSpace::Screen {
+                self.push_debug_label(&mut world_debug, label, screen_size);
+            }
+        }
+        world_debug.build(device, queue, &mut self.world_debug);
+
+        while self.view_uniforms.len() < scene.views.len() {
+            self.view_uniforms
+                .push(ViewUniform::new(device, &self.uniform_bind_group_layout));
+            self.view_skies.push(QuadBatch::new(device));
+        }
+
+        for (view, uniform) in scene.views.iter().zip(self.view_uniforms.iter()) {
+            queue.write_buffer(&uniform.buffer, 0, bytemuck::bytes_of(&view.camera_uniform));
+        }
 
         for sprite in self.sprites.iter_mut() {
             if let Some(instances) = scene.sprite_instances.get(&sprite.id) {
-                sprite.update_instance_buffer(instances.clone(), queue);
+                sprite.update_instance_buffer(device, queue, instances);
             }
         }
 
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        // Targets are cleared by the first view drawn into them, later views draw on top.
+        let mut cleared: Vec<&RenderTarget> = vec![];
+
+        for (i, (view, uniform)) in scene
+            .views
+            .iter()
+            .zip(self.view_uniforms.iter())
+            .enumerate()
         {
+            let (colour, depth, (width, height)) = match &view.target {
+                RenderTarget::Screen => (
+                    &frame.view,
+                    &self.depth_texture.view,
+                    (sc_desc.width, sc_desc.height),
+                ),
+                RenderTarget::Texture(id) => match self.render_textures.get(id) {
+                    Some(texture) => (
+                        &texture.view,
+                        &texture.depth.view,
+                        (texture.width, texture.height),
+                    ),
+                    None => continue,
+                },
+            };
+
+            let x = view.viewport.min.x * width as f32;
+            let y = view.viewport.min.y * height as f32;
+            let w = view.viewport.size().x * width as f32;
+            let h = view.viewport.size().y * height as f32;
+            if w < 1.0 || h < 1.0 {
+                continue;
+            }
+
+            let first = !cleared.contains(&&view.target);
+            let load = if first {
+                cleared.push(&view.target);
+                let colour = view.sky.as_ref().map_or(CLEAR_COLOUR, |sky| {
+                    let [r, g, b, a] = sky.clear_colour;
+                    wgpu::Color {
+                        r: r as f64,
+                        g: g as f64,
+                        b: b as f64,
+                        a: a as f64,
+                    }
+                });
+                wgpu::LoadOp::Clear(colour)
+            } else {
+                wgpu::LoadOp::Load
+            };
+            let mut sky = QuadBatchBuilder::new();
+            if let Some(view_sky) = &view.sky {
+                self.push_sky(&mut sky, view_sky, &view.target, !first, (w, h));
+            }
+            sky.build(device, queue, &mut self.view_skies[i]);
+
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
-                    attachment: &frame.view,
+                    attachment: colour,
                     resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 0.0,
-                        }),
-                        store: true,
-                    },
+                    ops: wgpu::Operations { load, store: true },
                 }],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
-                    attachment: &self.depth_texture.view,
+                    attachment: depth,
                     depth_ops: Some(wgpu::Operations {
                         load: wgpu::LoadOp::Clear(1.0),
                         store: true,
@@ -204,15 +622,176 @@ impl Renderer {
                 }),
             });
 
+            rpass.set_viewport(x, y, w, h, 0.0, 1.0);
+
+            // The sky doesn't write depth, so every sprite goes over it.
+            let sky = &self.view_skies[i];
+            if !sky.is_empty() {
+                rpass.set_pipeline(&self.quad_pipeline);
+                rpass.draw_quads(
+                    sky,
+                    &self.fonts,
+                    &self.sprites,
+                    &self.blank_bind_group,
+                    &uniform.bind_group,
+                );
+            }
+
             rpass.set_pipeline(&self.pipeline);
 
-            for sprite in self.sprites.iter_mut() {
-                if let Some(instances) = scene.sprite_instances.get(&sprite.id) {
-                    rpass.draw_sprite(sprite, 0..instances.len() as u32, &self.uniform_bind_group);
+            for sprite in self.sprites.iter() {
+                // A texture can't be sampled while it is being rendered to.
+                if let RenderTarget::Texture(id) = &view.target {
+                    if *id == sprite.id {
+                        continue;
+                    }
                 }
+                if let Some(instances) = view.instances.get(&sprite.id) {
+                    rpass.draw_sprite(sprite, instances.clone(), &uniform.bind_group);
+                }
+            }
+
+            if !self.world_quads.is_empty() {
+                rpass.set_pipeline(&self.quad_pipeline);
+                rpass.draw_quads(
+                    &self.world_quads,
+                    &self.fonts,
+                    &self.sprites,
+                    &self.blank_bind_group,
+                    &uniform.bind_group,
+                );
+            }
+
+            if !self.world_debug.is_empty() {
+                rpass.set_pipeline(&self.debug_pipeline);
+                rpass.draw_quads(
+                    &self.world_debug,
+                    &self.fonts,
+                    &self.sprites,
+                    &self.blank_bind_group,
+                    &uniform.bind_group,
+                );
+            }
+        }
+
+        // The UI goes over every view. Clear the screen first if no view drew to it,
+        // rather than leave the last frame up.
+        let screen_cleared = cleared.contains(&&RenderTarget::Screen);
+        if !self.ui.is_empty() || !screen_cleared {
+            let load = if screen_cleared {
+                wgpu::LoadOp::Load
+            } else {
+                wgpu::LoadOp::Clear(CLEAR_COLOUR)
+            };
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: &frame.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load, store: true },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                    attachment: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            if !self.ui.is_empty() {
+                rpass.set_pipeline(&self.quad_pipeline);
+                rpass.draw_quads(
+                    &self.ui,
+                    &self.fonts,
+                    &self.sprites,
+                    &self.blank_bind_group,
+                    &self.overlay_uniform.bind_group,
+                );
             }
         }
 
         queue.submit(Some(encoder.finish()));
     }
 }
+
+// World space lines are a sprite pixel wide, screen space ones two pixels.
+fn push_debug_line(batch: &mut QuadBatchBuilder, line: &DebugLine, screen_size: (u32, u32)) {
+    match line.space {
+        DebugSpace::World(depth) => {
+            // Anchored at the middle, like a sprite quad at its centre.
+            let middle = (line.from + line.to) / 2.0;
+            let placement = Placement::World {
+                anchor: Vec3::new(middle.x, middle.y, depth),
+                metres: 1.0 / PIXELS_PER_METRE as f32,
+            };
+            let to_pixels =
+                |point: Vec2| (point - middle) * Vec2::new(1.0, -1.0) * PIXELS_PER_METRE as f32;
+            batch.push_line(
+                &placement,
+                to_pixels(line.from),
+                to_pixels(line.to),
+                1.0,
+                line.colour,
+            );
+        }
+        DebugSpace::Screen => {
+            let placement = Placement::Screen {
+                position: Vec2::zero(),
+                scale: 1.0,
+                size: screen_size,
+            };
+            batch.push_line(&placement, line.from, line.to, 2.0, line.colour);
+        }
+    }
+}
+
+// The pipeline for glyphs, UI and debug quads. They never write depth, and test it with
+// `depth_compare`.
+fn create_quad_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    depth_compare: wgpu::CompareFunction,
+) -> wgpu::RenderPipeline {
+    let vs_module = device.create_shader_module(&wgpu::include_spirv!("../shaders/quad.vert.spv"));
+    let fs_module = device.create_shader_module(&wgpu::include_spirv!("../shaders/quad.frag.spv"));
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Quads"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &vs_module,
+            entry_point: "main",
+            buffers: &[QuadVertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &fs_module,
+            entry_point: "main",
+            targets: &[wgpu::ColorTargetState {
+                format,
+                color_blend: wgpu::BlendState {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::OneMinusSrcAlpha,
+                    operation: BlendOperation::Add,
+                },
+                alpha_blend: wgpu::BlendState {
+                    src_factor: BlendFactor::One,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Min,
+                },
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DepthTexture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: Default::default(),
+            clamp_depth: false,
+        }),
+        multisample: wgpu::MultisampleState::default(),
+    })
+}
@@ -1,7 +1,73 @@
-use crate::gpu_primitives::{CameraUniform, InstanceRaw};
-use std::collections::HashMap;
+use crate::{
+    asset::{FontAsset, SpriteAsset},
+    camera::{sky::Sky, RenderTarget},
+    debug::{DebugLabel, DebugLine},
+    gpu_primitives::{CameraUniform, InstanceRaw},
+    text::Text,
+    ui::UiImage,
+    Rect,
+};
+use glam::{Vec2, Vec3};
+use std::{collections::HashMap, ops::Range};
 
 pub struct Scene {
     pub sprite_instances: HashMap<String, Vec<InstanceRaw>>,
+    /// Sprites drawn at a size other than their image's, drawn with world space text.
+    pub sized_sprites: Vec<SizedSprite>,
+    /// One per active camera, in the order they should be drawn.
+    pub views: Vec<View>,
+    /// Sprite assets streamed in or out since the last frame, applied in order before drawing.
+    pub asset_changes: Vec<AssetChange>,
+    /// World space text.
+    pub texts: Vec<TextItem>,
+    /// The screen overlay, in the order it should be drawn.
+    pub ui: Vec<UiItem>,
+    pub debug_lines: Vec<DebugLine>,
+    pub debug_labels: Vec<DebugLabel>,
+}
+
+pub enum AssetChange {
+    Load(SpriteAsset),
+    Unload(String),
+    LoadFont(FontAsset),
+}
+
+pub struct SizedSprite {
+    pub sprite: String,
+    pub frame_id: u32,
+    /// Of the centre.
+    pub position: Vec3,
+    /// In metres.
+    pub size: Vec2,
+    /// Sprite pixels per image pixel for nine-slice borders.
+    pub border_scale: f32,
+}
+
+pub struct TextItem {
+    pub text: Text,
+    /// Where the text hangs from.
+    pub anchor: Vec3,
+}
+
+pub struct UiItem {
+    /// In pixels from the top left of the screen.
+    pub rect: Rect,
+    pub content: UiContent,
+}
+
+pub enum UiContent {
+    Image(UiImage),
+    /// Laid out from the top of the rect, on the side given by its alignment.
+    Text(Text),
+}
+
+pub struct View {
     pub camera_uniform: CameraUniform,
+    /// Area of the target to draw into, as fractions of its size from the top left.
+    pub viewport: Rect,
+    pub target: RenderTarget,
+    /// Drawn behind the view's sprites, the view clears to transparent black without one.
+    pub sky: Option<Sky>,
+    /// The instances of each sprite in [`Scene::sprite_instances`] this view's camera sees.
+    pub instances: HashMap<String, Range<u32>>,
 }
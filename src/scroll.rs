@@ -0,0 +1,44 @@
+use crate::Position;
+use glam::Vec2;
+use hecs::World;
+use std::time::{Duration, Instant};
+
+/// Moves an entity through the world at a constant speed, in metres per second, for layers
+/// like clouds that drift whether or not the camera moves. The movement happens in the world,
+/// so the layer still gets its parallax on top: on screen it drifts at this speed scaled by the
+/// camera's [`crate::camera::ParallaxCamera::scroll_factor`] at its depth.
+#[derive(Clone, Copy, Debug)]
+pub struct ScrollVelocity(pub Vec2);
+
+/// Scrolls a sprite's texture across its quad, wrapping around, while the quad stays put. For
+/// things like a flowing river. Works on sprites and repeating layers.
+#[derive(Clone, Copy, Debug)]
+pub struct UvScroll {
+    /// Texture widths and heights per second.
+    pub velocity: Vec2,
+    /// How far the texture has scrolled, always between 0 and 1.
+    pub offset: Vec2,
+}
+
+impl UvScroll {
+    pub fn new(velocity: Vec2) -> Self {
+        UvScroll {
+            velocity,
+            offset: Vec2::zero(),
+        }
+    }
+}
+
+pub fn scroll_layers(world: &World, dt: Duration, _instant: Instant) {
+    let dt = dt.as_secs_f32();
+
+    for (_, (pos, velocity)) in world.query::<(&mut Position, &ScrollVelocity)>().iter() {
+        pos.0.x += velocity.0.x * dt;
+        pos.0.y += velocity.0.y * dt;
+    }
+
+    for (_, scroll) in world.query::<&mut UvScroll>().iter() {
+        let offset = scroll.offset + scroll.velocity * dt;
+        scroll.offset = Vec2::new(offset.x.rem_euclid(1.0), offset.y.rem_euclid(1.0));
+    }
+}
@@ -0,0 +1,532 @@
+use crate::{camera::ParallaxCamera, Rect};
+use glam::{Mat4, Vec2, Vec4};
+use hecs::{Entity, World};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Range,
+};
+
+type Cell = (i32, i32);
+
+/// A uniform grid over the x/y plane, one per band of depth, holding the bounds of every sprite
+/// in the world. [`Game`](crate::Game) keeps one up to date on a singleton entity, which systems
+/// can reach with [`SpatialIndex::read`].
+pub struct SpatialIndex {
+    cell_size: f32,
+    band_depth: f32,
+    bands: BTreeMap<i32, Band>,
+    entries: HashMap<Entity, Entry>,
+    stamp: u64,
+}
+
+#[derive(Default)]
+struct Band {
+    cells: HashMap<Cell, Vec<Entity>>,
+    // Largest half extents of anything in the band, used to pad camera queries.
+    margin: Vec2,
+}
+
+struct Entry {
+    bounds: Rect,
+    depth: f32,
+    stamp: u64,
+}
+
+impl SpatialIndex {
+    pub fn new(cell_size: f32, band_depth: f32) -> Self {
+        SpatialIndex {
+            cell_size,
+            band_depth,
+            bands: BTreeMap::new(),
+            entries: HashMap::new(),
+            stamp: 0,
+        }
+    }
+
+    /// Calls `f` with the index [`Game`](crate::Game) maintains in `world`.
+    pub fn read<R>(world: &World, f: impl FnOnce(&SpatialIndex) -> R) -> Option<R> {
+        world
+            .query::<&SpatialIndex>()
+            .iter()
+            .next()
+            .map(|(_, index)| f(index))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds `entity` with its world space `bounds` at `depth`, replacing any previous entry.
+    pub fn insert(&mut self, entity: Entity, bounds: Rect, depth: f32) {
+        if let Some(entry) = self.entries.get_mut(&entity) {
+            entry.stamp = self.stamp;
+            if entry.bounds == bounds && self.band(entry.depth) == self.band(depth) {
+                entry.depth = depth;
+                return;
+            }
+        }
+        self.remove(entity);
+
+        let band = self.band(depth);
+        let (min, max) = self.cell_range(&bounds);
+        let band = self.bands.entry(band).or_default();
+        band.margin = band.margin.max(bounds.size() / 2.0);
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                band.cells.entry((x, y)).or_default().push(entity);
+            }
+        }
+
+        self.entries.insert(
+            entity,
+            Entry {
+                bounds,
+                depth,
+                stamp: self.stamp,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        let entry = match self.entries.remove(&entity) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        let band_key = self.band(entry.depth);
+        let (min, max) = self.cell_range(&entry.bounds);
+        if let Some(band) = self.bands.get_mut(&band_key) {
+            for x in min.0..=max.0 {
+                for y in min.1..=max.1 {
+                    if let Some(entities) = band.cells.get_mut(&(x, y)) {
+                        entities.retain(|e| *e != entity);
+                        if entities.is_empty() {
+                            band.cells.remove(&(x, y));
+                        }
+                    }
+                }
+            }
+            if band.cells.is_empty() {
+                self.bands.remove(&band_key);
+            } else {
+                // One big sprite leaving shouldn't keep widening every camera query.
+                let half = entry.bounds.size() / 2.0;
+                if half.x >= band.margin.x || half.y >= band.margin.y {
+                    let entries = &self.entries;
+                    band.margin = band
+                        .cells
+                        .values()
+                        .flatten()
+                        .fold(Vec2::zero(), |margin, entity| {
+                            margin.max(entries[entity].bounds.size() / 2.0)
+                        });
+                }
+            }
+        }
+        true
+    }
+
+    /// The bounds and depth `entity` was indexed with.
+    pub fn get(&self, entity: Entity) -> Option<(Rect, f32)> {
+        self.entries
+            .get(&entity)
+            .map(|entry| (entry.bounds, entry.depth))
+    }
+
+    /// Everything whose bounds overlap `region` with a depth in `depths`.
+    pub fn query_region(&self, region: &Rect, depths: Range<f32>) -> Vec<Entity> {
+        self.candidates(region, &depths)
+            .into_iter()
+            .filter(|entity| self.entries[entity].bounds.intersects(region))
+            .collect()
+    }
+
+    /// Everything whose bounds come within `radius` of `centre` with a depth in `depths`.
+    pub fn query_radius(&self, centre: Vec2, radius: f32, depths: Range<f32>) -> Vec<Entity> {
+        let region = Rect::from_centre(centre, Vec2::splat(radius));
+        self.candidates(&region, &depths)
+            .into_iter()
+            .filter(|entity| self.entries[entity].bounds.distance_to(centre) <= radius)
+            .collect()
+    }
+
+    /// Everything whose bounds are hit by a ray from `origin` along `dir` within
+    /// `max_distance`, with a depth in `depths`, nearest first along with the distance to the
+    /// hit.
+    pub fn query_ray(
+        &self,
+        origin: Vec2,
+        dir: Vec2,
+        max_distance: f32,
+        depths: Range<f32>,
+    ) -> Vec<(Entity, f32)> {
+        let dir = dir.normalize();
+        let end = origin + dir * max_distance;
+        let region = Rect::new(origin.min(end), origin.max(end));
+
+        let mut hits: Vec<(Entity, f32)> = self
+            .candidates(&region, &depths)
+            .into_iter()
+            .filter_map(|entity| {
+                ray_hit(&self.entries[&entity].bounds, origin, dir)
+                    .filter(|distance| *distance <= max_distance)
+                    .map(|distance| (entity, distance))
+            })
+            .collect();
+        hits.sort_by(|a, b| a.1.partial_cmp(&b.1).expect("hit distances are not NaN"));
+        hits
+    }
+
+    /// Everything that might be on screen for `cam`, band by band using the region of the x/y
+    /// plane the parallax projection shows at that depth, ordered by entity id. The bands are
+    /// depths along z, so a camera that doesn't look down the z axis gets everything.
+    pub fn query_visible(&self, cam: &ParallaxCamera) -> Vec<Entity> {
+        if !cam.looks_along_z() {
+            let mut all: Vec<Entity> = self.entries.keys().copied().collect();
+            all.sort_by_key(|entity| entity.id());
+            return all;
+        }
+
+        let mut visible = HashSet::new();
+        for (band, contents) in self.bands.iter() {
+            let near = *band as f32 * self.band_depth - cam.eye.z;
+            let far = near + self.band_depth;
+            let region = cam
+                .visible_region(near, contents.margin)
+                .union(&cam.visible_region(far, contents.margin));
+            self.collect(contents, &region, &mut visible);
+        }
+        let mut visible: Vec<Entity> = visible
+            .into_iter()
+            .filter(|entity| {
+                let entry = &self.entries[entity];
+                let depth = entry.depth - cam.eye.z;
+                cam.visible_region(depth, entry.bounds.size() / 2.0)
+                    .intersects(&entry.bounds)
+            })
+            .collect();
+        visible.sort_by_key(|entity| entity.id());
+        visible
+    }
+
+    // Marks the start of a rebuild, see `sweep`.
+    pub(crate) fn begin_update(&mut self) {
+        self.stamp += 1;
+    }
+
+    // Drops everything not inserted since `begin_update`.
+    pub(crate) fn sweep(&mut self) {
+        let stale: Vec<Entity> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.stamp != self.stamp)
+            .map(|(entity, _)| *entity)
+            .collect();
+        for entity in stale {
+            self.remove(entity);
+        }
+    }
+
+    fn band(&self, depth: f32) -> i32 {
+        (depth / self.band_depth).floor() as i32
+    }
+
+    fn cell_range(&self, rect: &Rect) -> (Cell, Cell) {
+        let cell = |v: f32| (v / self.cell_size).floor() as i32;
+        (
+            (cell(rect.min.x), cell(rect.min.y)),
+            (cell(rect.max.x), cell(rect.max.y)),
+        )
+    }
+
+    fn candidates(&self, region: &Rect, depths: &Range<f32>) -> HashSet<Entity> {
+        let mut found = HashSet::new();
+        match depths.start.partial_cmp(&depths.end) {
+            Some(Ordering::Less) | Some(Ordering::Equal) => (),
+            _ => return found,
+        }
+        let bands = self.band(depths.start)..=self.band(depths.end);
+        for (_, band) in self.bands.range(bands) {
+            self.collect(band, region, &mut found);
+        }
+        found.retain(|entity| depths.contains(&self.entries[entity].depth));
+        found
+    }
+
+    fn collect(&self, band: &Band, region: &Rect, found: &mut HashSet<Entity>) {
+        let (min, max) = self.cell_range(region);
+        let cell_count = (max.0 as i64 - min.0 as i64 + 1) * (max.1 as i64 - min.1 as i64 + 1);
+
+        // Big regions are cheaper to answer by walking the occupied cells.
+        if cell_count > band.cells.len() as i64 {
+            for ((x, y), entities) in band.cells.iter() {
+                if (min.0..=max.0).contains(x) && (min.1..=max.1).contains(y) {
+                    found.extend(entities.iter().copied());
+                }
+            }
+        } else {
+            for x in min.0..=max.0 {
+                for y in min.1..=max.1 {
+                    if let Some(entities) = band.cells.get(&(x, y)) {
+                        found.extend(entities.iter().copied());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The x/y bounds of a sprite quad with `half_extents` once `model` is applied.
+pub(crate) fn world_bounds(model: &Mat4, half_extents: Vec2) -> Rect {
+    let mut min = Vec2::splat(f32::INFINITY);
+    let mut max = Vec2::splat(f32::NEG_INFINITY);
+    for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter() {
+        let corner = *model * Vec4::new(x * half_extents.x, y * half_extents.y, 0.0, 1.0);
+        min = min.min(Vec2::new(corner.x, corner.y));
+        max = max.max(Vec2::new(corner.x, corner.y));
+    }
+    Rect::new(min, max)
+}
+
+// Slab test, the distance along the ray to where it enters `bounds`.
+fn ray_hit(bounds: &Rect, origin: Vec2, dir: Vec2) -> Option<f32> {
+    let mut t_min: f32 = 0.0;
+    let mut t_max = f32::INFINITY;
+    for &(o, d, min, max) in [
+        (origin.x, dir.x, bounds.min.x, bounds.max.x),
+        (origin.y, dir.y, bounds.min.y, bounds.max.y),
+    ]
+    .iter()
+    {
+        if d.abs() < f32::EPSILON {
+            if o < min || o > max {
+                return None;
+            }
+        } else {
+            let t1 = (min - o) / d;
+            let t2 = (max - o) / d;
+            t_min = t_min.max(t1.min(t2));
+            t_max = t_max.min(t1.max(t2));
+        }
+    }
+    if t_min <= t_max {
+        Some(t_min)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    fn rect(min: (f32, f32), max: (f32, f32)) -> Rect {
+        Rect::new(Vec2::new(min.0, min.1), Vec2::new(max.0, max.1))
+    }
+
+    fn sorted(mut entities: Vec<Entity>) -> Vec<Entity> {
+        entities.sort_by_key(|entity| entity.id());
+        entities
+    }
+
+    #[test]
+    fn insert_move_and_remove() {
+        let mut world = World::new();
+        let entity = world.spawn(());
+        let mut index = SpatialIndex::new(1.0, 10.0);
+
+        index.insert(entity, rect((0.0, 0.0), (1.0, 1.0)), 5.0);
+        assert_eq!(
+            index.query_region(&rect((0.2, 0.2), (0.8, 0.8)), 0.0..10.0),
+            vec![entity]
+        );
+
+        // Moving drops it from the cells it left.
+        index.insert(entity, rect((4.0, 0.0), (5.0, 1.0)), 5.0);
+        assert!(index
+            .query_region(&rect((0.2, 0.2), (0.8, 0.8)), 0.0..10.0)
+            .is_empty());
+        assert_eq!(
+            index.query_region(&rect((4.2, 0.2), (4.8, 0.8)), 0.0..10.0),
+            vec![entity]
+        );
+
+        // And from the band it left.
+        index.insert(entity, rect((4.0, 0.0), (5.0, 1.0)), 25.0);
+        assert!(index
+            .query_region(&rect((4.2, 0.2), (4.8, 0.8)), 0.0..10.0)
+            .is_empty());
+        assert_eq!(
+            index.query_region(&rect((4.2, 0.2), (4.8, 0.8)), 20.0..30.0),
+            vec![entity]
+        );
+        assert_eq!(
+            index.get(entity),
+            Some((rect((4.0, 0.0), (5.0, 1.0)), 25.0))
+        );
+        assert_eq!(index.bands.len(), 1);
+        assert_eq!(index.len(), 1);
+
+        assert!(index.remove(entity));
+        assert!(!index.remove(entity));
+        assert!(index.is_empty());
+        assert!(index.bands.is_empty());
+        assert!(index
+            .query_region(&rect((-100.0, -100.0), (100.0, 100.0)), 0.0..100.0)
+            .is_empty());
+    }
+
+    #[test]
+    fn margin_shrinks_when_the_largest_sprite_goes() {
+        let mut world = World::new();
+        let (big, small) = (world.spawn(()), world.spawn(()));
+        let mut index = SpatialIndex::new(1.0, 10.0);
+
+        index.insert(big, rect((-5.0, -3.0), (5.0, 3.0)), 1.0);
+        index.insert(small, rect((0.0, 0.0), (1.0, 0.5)), 2.0);
+        assert_eq!(index.bands[&0].margin, Vec2::new(5.0, 3.0));
+
+        index.remove(big);
+        assert_eq!(index.bands[&0].margin, Vec2::new(0.5, 0.25));
+    }
+
+    #[test]
+    fn region_and_radius_queries_include_cell_edges() {
+        let mut world = World::new();
+        let entity = world.spawn(());
+        let mut index = SpatialIndex::new(1.0, 10.0);
+        index.insert(entity, rect((1.0, 0.0), (2.0, 1.0)), 0.0);
+
+        // Touching either side of the cells it spans counts as overlapping.
+        assert_eq!(
+            index.query_region(&rect((0.0, 0.0), (1.0, 1.0)), 0.0..1.0),
+            vec![entity]
+        );
+        assert_eq!(
+            index.query_region(&rect((2.0, 0.0), (3.0, 1.0)), 0.0..1.0),
+            vec![entity]
+        );
+        assert!(index
+            .query_region(&rect((0.0, 0.0), (0.99, 1.0)), 0.0..1.0)
+            .is_empty());
+        assert!(index
+            .query_region(&rect((2.01, 0.0), (3.0, 1.0)), 0.0..1.0)
+            .is_empty());
+
+        // A region far bigger than the occupied cells walks those instead, with the same result.
+        let huge = rect((-1000.0, -1000.0), (1000.0, 1000.0));
+        assert_eq!(index.query_region(&huge, 0.0..1.0), vec![entity]);
+
+        assert_eq!(
+            index.query_radius(Vec2::new(0.0, 0.5), 1.0, 0.0..1.0),
+            vec![entity]
+        );
+        assert_eq!(
+            index.query_radius(Vec2::new(3.0, 2.0), 1.5, 0.0..1.0),
+            vec![entity]
+        );
+        assert!(index
+            .query_radius(Vec2::new(0.0, 0.5), 0.99, 0.0..1.0)
+            .is_empty());
+        assert!(index
+            .query_radius(Vec2::new(3.0, 2.0), 1.4, 0.0..1.0)
+            .is_empty());
+    }
+
+    #[test]
+    fn ray_hits_are_nearest_first_within_max_distance() {
+        let mut world = World::new();
+        let boxes: Vec<Entity> = (0..3).map(|_| world.spawn(())).collect();
+        let mut index = SpatialIndex::new(1.0, 10.0);
+        // Inserted furthest first so the order can't come from insertion.
+        for (i, &entity) in boxes.iter().enumerate().rev() {
+            let x = 2.0 + 3.0 * i as f32;
+            index.insert(entity, rect((x, -0.5), (x + 1.0, 0.5)), 0.0);
+        }
+
+        let hits = index.query_ray(Vec2::new(0.0, 0.0), Vec2::new(2.0, 0.0), 6.0, 0.0..1.0);
+        let entities: Vec<Entity> = hits.iter().map(|(entity, _)| *entity).collect();
+        assert_eq!(entities, vec![boxes[0], boxes[1]]);
+        assert!((hits[0].1 - 2.0).abs() < 1e-5);
+        assert!((hits[1].1 - 5.0).abs() < 1e-5);
+
+        // Rays that miss, or stop short, hit nothing.
+        assert!(index
+            .query_ray(Vec2::new(0.0, 2.0), Vec2::new(1.0, 0.0), 100.0, 0.0..1.0)
+            .is_empty());
+        assert!(index
+            .query_ray(Vec2::new(0.0, 0.0), Vec2::new(1.0, 0.0), 1.9, 0.0..1.0)
+            .is_empty());
+    }
+
+    #[test]
+    fn empty_and_reversed_depth_ranges_find_nothing() {
+        let mut world = World::new();
+        let entity = world.spawn(());
+        let mut index = SpatialIndex::new(1.0, 10.0);
+        index.insert(entity, rect((0.0, 0.0), (1.0, 1.0)), 5.0);
+        let region = rect((0.0, 0.0), (1.0, 1.0));
+
+        assert_eq!(index.query_region(&region, 5.0..5.1), vec![entity]);
+        assert!(index.query_region(&region, 5.0..5.0).is_empty());
+        assert!(index.query_region(&region, 10.0..0.0).is_empty());
+        assert!(index.query_region(&region, f32::NAN..10.0).is_empty());
+        assert!(index.query_radius(Vec2::zero(), 5.0, 10.0..0.0).is_empty());
+    }
+
+    #[test]
+    fn sweep_drops_what_was_not_reinserted() {
+        let mut world = World::new();
+        let (kept, dropped) = (world.spawn(()), world.spawn(()));
+        let mut index = SpatialIndex::new(1.0, 10.0);
+        index.insert(kept, rect((0.0, 0.0), (1.0, 1.0)), 0.0);
+        index.insert(dropped, rect((0.0, 0.0), (1.0, 1.0)), 15.0);
+
+        index.begin_update();
+        index.insert(kept, rect((0.0, 0.0), (1.0, 1.0)), 0.0);
+        index.sweep();
+
+        assert_eq!(index.len(), 1);
+        assert!(index.get(kept).is_some());
+        assert!(index.get(dropped).is_none());
+        assert_eq!(index.bands.len(), 1);
+    }
+
+    #[test]
+    fn visible_matches_a_brute_force_check() {
+        let mut world = World::new();
+        let mut index = SpatialIndex::new(2.0, 10.0);
+        let mut entries = vec![];
+        for i in 0..400 {
+            let entity = world.spawn(());
+            // A spread of positions, sizes and depths that puts some on each side of every
+            // screen edge.
+            let x = ((i * 37) % 201) as f32 - 100.0;
+            let y = ((i * 53) % 81) as f32 - 40.0;
+            let half = Vec2::new(0.5 + (i % 4) as f32, 0.5 + (i % 3) as f32);
+            let depth = ((i * 7) % 120) as f32;
+            let bounds = Rect::from_centre(Vec2::new(x, y), half);
+            index.insert(entity, bounds, depth);
+            entries.push((entity, bounds, depth));
+        }
+
+        let mut cam =
+            ParallaxCamera::new(Vec3::new(8.0, -3.0, -10.0), Vec3::unit_z(), 0.8, 0.1, 500.0);
+        cam.resolution = (800, 600);
+        let expected: Vec<Entity> = entries
+            .iter()
+            .filter(|(_, bounds, depth)| {
+                cam.visible_region(depth - cam.eye.z, bounds.size() / 2.0)
+                    .intersects(bounds)
+            })
+            .map(|(entity, _, _)| *entity)
+            .collect();
+        assert!(!expected.is_empty() && expected.len() < entries.len());
+        assert_eq!(index.query_visible(&cam), sorted(expected));
+    }
+}
@@ -1,123 +1,320 @@
 use crate::{
-    asset::SpriteAsset,
+    asset::{Insets, LoadError, SpriteAsset, TileGrid},
     gpu_primitives::{Index, InstanceRaw, Vertex},
     renderer::TEXTURE_ARRAY_SIZE,
-    texture::ArrayTexture,
+    texture::{ArrayTexture, RenderTexture},
 };
-use image::GenericImageView;
-use std::{convert::TryInto, ops::Range};
+use glam::Vec2;
+use image::RgbaImage;
+use std::{convert::TryInto, num::NonZeroU32, ops::Range, path::PathBuf};
 use wgpu::{util::DeviceExt, TextureView};
 
-pub const MAX_INSTANCES: u64 = 1024;
+// Instances a sprite has room for until a frame needs more.
+const INITIAL_INSTANCES: u64 = 1024;
 pub const PIXELS_PER_METRE: u32 = 32;
 
+/// Half the width and height in metres of the quad a sprite of `width` by `height` pixels is
+/// drawn on, before scaling.
+pub fn half_extents((width, height): (u32, u32)) -> Vec2 {
+    Vec2::new(width as f32, height as f32) / (2.0 * PIXELS_PER_METRE as f32)
+}
+
 pub struct Sprite {
     pub id: String,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub instance_buffer: wgpu::Buffer,
+    /// How many instances fit in the instance buffer, it grows when a frame needs more.
+    pub instance_capacity: u64,
     pub bind_group: wgpu::BindGroup,
+    /// Normal and emissive maps, from [`LightingMaps::bind`].
+    pub maps_bind_group: wgpu::BindGroup,
+    /// Size of a frame in pixels.
+    pub size: (u32, u32),
+    pub nine_slice: Option<Insets>,
     num_indices: u32,
 }
 
 impl Sprite {
     pub fn new(
-        device: &mut wgpu::Device,
+        device: &wgpu::Device,
         queue: &wgpu::Queue,
         sprite_bind_group_layout: &wgpu::BindGroupLayout,
+        lighting_maps: &LightingMaps,
         asset: SpriteAsset,
-    ) -> Self {
-        let path = asset
-            .frames
-            .first()
-            .expect("at least 1 animated sprite file was specified");
-        let image = image::open(path).unwrap();
-        let (tex_width, tex_height) = image.dimensions();
-        let (vertex_data, index_data) = create_vertices(tex_width, tex_height, PIXELS_PER_METRE);
+    ) -> Result<Self, LoadError> {
+        let (tex_width, tex_height) = asset.try_size()?;
 
-        let textures: Vec<ArrayTexture> = asset
+        let load = |path: &PathBuf| -> Result<RgbaImage, LoadError> {
+            Ok(image::open(path)?.into_rgba8())
+        };
+        let textures = asset
             .frames
             .iter()
-            .map(|path| {
-                let image = image::open(path)
-                    .unwrap_or_else(|_| panic!("animated sprite exists: {:?}", path.to_str()));
-                ArrayTexture::new(&device, &queue, image.into_rgba8())
-            })
-            .collect();
-
-        let mut i = 0;
-        let mut views: Vec<&TextureView> = vec![];
-
-        for at in textures.iter() {
-            views.push(&at.view);
-            i += 1;
-        }
+            .map(|path| Ok(ArrayTexture::new(&device, &queue, load(path)?)))
+            .collect::<Result<Vec<_>, LoadError>>()?;
+        // Maps line up with frames, so if any is missing the sprite gets the stand-ins for all
+        // of them rather than failing to load.
+        let normal_maps = asset
+            .normal_maps
+            .iter()
+            .map(|path| Ok(ArrayTexture::linear(&device, &queue, load(path)?)))
+            .collect::<Result<Vec<_>, LoadError>>()
+            .unwrap_or_else(|err| {
+                log::warn!("sprite {} lit flat: {}", asset.id, err);
+                vec![]
+            });
+        let emissive_maps = asset
+            .emissive_maps
+            .iter()
+            .map(|path| Ok(ArrayTexture::new(&device, &queue, load(path)?)))
+            .collect::<Result<Vec<_>, LoadError>>()
+            .unwrap_or_else(|err| {
+                log::warn!("sprite {} without glow: {}", asset.id, err);
+                vec![]
+            });
+
+        let views: Vec<&TextureView> = textures.iter().map(|at| &at.view).collect();
+        let maps_bind_group = lighting_maps.bind(
+            device,
+            &normal_maps.iter().map(|at| &at.view).collect::<Vec<_>>(),
+            &emissive_maps.iter().map(|at| &at.view).collect::<Vec<_>>(),
+        );
 
-        for _ in i..TEXTURE_ARRAY_SIZE {
-            views.push(
-                &textures
+        let (vertex_data, index_data) = match &asset.tiles {
+            Some(grid) => {
+                let tileset = textures
                     .first()
-                    .expect("at least one texture provided")
-                    .view,
-            )
-        }
+                    .expect("tilemap has a tileset image")
+                    .size;
+                create_tile_vertices(grid, tileset, PIXELS_PER_METRE)
+            }
+            None => create_vertices(tex_width, tex_height, PIXELS_PER_METRE),
+        };
 
-        let views: [&TextureView; TEXTURE_ARRAY_SIZE as usize] = views
-            .try_into()
-            .expect("correct number of textures views provided");
+        let mut sprite = Self::from_views(
+            device,
+            sprite_bind_group_layout,
+            &asset.id,
+            (&vertex_data, &index_data),
+            &views,
+            maps_bind_group,
+            textures
+                .first()
+                .map_or((tex_width, tex_height), |texture| texture.size),
+        );
+        sprite.nine_slice = asset.nine_slice;
+        Ok(sprite)
+    }
 
+    /// A sprite that samples what a camera rendered into `texture`.
+    pub fn from_render_texture(
+        device: &wgpu::Device,
+        sprite_bind_group_layout: &wgpu::BindGroupLayout,
+        lighting_maps: &LightingMaps,
+        id: &str,
+        texture: &RenderTexture,
+    ) -> Self {
+        let (vertex_data, index_data) =
+            create_vertices(texture.width, texture.height, PIXELS_PER_METRE);
+
+        Self::from_views(
+            device,
+            sprite_bind_group_layout,
+            id,
+            (&vertex_data, &index_data),
+            &[&texture.view],
+            lighting_maps.bind(device, &[], &[]),
+            (texture.width, texture.height),
+        )
+    }
+
+    fn from_views(
+        device: &wgpu::Device,
+        sprite_bind_group_layout: &wgpu::BindGroupLayout,
+        id: &str,
+        (vertex_data, index_data): (&[Vertex], &[Index]),
+        frames: &[&TextureView],
+        maps_bind_group: wgpu::BindGroup,
+        size: (u32, u32),
+    ) -> Self {
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(&vertex_data),
+            contents: bytemuck::cast_slice(vertex_data),
             usage: wgpu::BufferUsage::VERTEX,
         });
 
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(&index_data),
+            contents: bytemuck::cast_slice(index_data),
             usage: wgpu::BufferUsage::INDEX,
         });
 
-        let instance_buf_size = MAX_INSTANCES * std::mem::size_of::<InstanceRaw>() as u64;
-        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Instance Buffer"),
-            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
-            size: instance_buf_size,
-            mapped_at_creation: false,
+        let instance_buffer = create_instance_buffer(device, INITIAL_INSTANCES);
+
+        let bind_group = texture_array_bind_group(device, sprite_bind_group_layout, frames);
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            instance_buffer,
+            instance_capacity: INITIAL_INSTANCES,
+            bind_group,
+            maps_bind_group,
+            size,
+            nine_slice: None,
+            num_indices: index_data.len() as u32,
+            id: id.to_string(),
+        }
+    }
+
+    pub fn update_instance_buffer(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        instances: &[InstanceRaw],
+    ) {
+        let needed = instances.len() as u64;
+        if needed > self.instance_capacity {
+            self.instance_capacity = needed.next_power_of_two();
+            self.instance_buffer = create_instance_buffer(device, self.instance_capacity);
+        }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(instances));
+    }
+}
+
+fn create_instance_buffer(device: &wgpu::Device, capacity: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Instance Buffer"),
+        usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        size: capacity * std::mem::size_of::<InstanceRaw>() as u64,
+        mapped_at_creation: false,
+    })
+}
+
+/// Repeats the first of `frames` to fill a texture array.
+fn fill_texture_array<'a>(
+    frames: &[&'a TextureView],
+) -> [&'a TextureView; TEXTURE_ARRAY_SIZE as usize] {
+    let mut views: Vec<&TextureView> = frames.to_vec();
+
+    for _ in frames.len() as u32..TEXTURE_ARRAY_SIZE {
+        views.push(frames.first().expect("at least one texture provided"))
+    }
+
+    views
+        .try_into()
+        .expect("correct number of textures views provided")
+}
+
+/// Binds `frames` as a sprite's texture array, repeating the first to fill it.
+pub(crate) fn texture_array_bind_group(
+    device: &wgpu::Device,
+    sprite_bind_group_layout: &wgpu::BindGroupLayout,
+    frames: &[&TextureView],
+) -> wgpu::BindGroup {
+    let views = fill_texture_array(frames);
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: sprite_bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureViewArray(&views),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&ArrayTexture::create_sampler(device)),
+            },
+        ],
+        label: None,
+    })
+}
+
+/// The layout of the sprite pipeline's normal and emissive maps, with stand-ins for sprites
+/// without them.
+pub(crate) struct LightingMaps {
+    pub layout: wgpu::BindGroupLayout,
+    flat: ArrayTexture,
+    dark: ArrayTexture,
+}
+
+impl LightingMaps {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let texture_array = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: Some(
+                NonZeroU32::new(TEXTURE_ARRAY_SIZE)
+                    .expect("array texture count should be a positive number"),
+            ),
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Lighting Maps"),
+            entries: &[
+                texture_array(0),
+                texture_array(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: false,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
         });
 
-        // Create bind group
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: sprite_bind_group_layout,
+        // Facing the camera, and not glowing.
+        let flat = RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255]));
+        let dark = RgbaImage::from_pixel(1, 1, image::Rgba([0, 0, 0, 255]));
+        LightingMaps {
+            layout,
+            flat: ArrayTexture::linear(device, queue, flat),
+            dark: ArrayTexture::new(device, queue, dark),
+        }
+    }
+    /// Binds a normal and emissive map for each frame, repeating the first of each to fill
+    /// their arrays. Sprites without either are flat or don't glow.
+    pub fn bind(
+        &self,
+        device: &wgpu::Device,
+        normal_maps: &[&TextureView],
+        emissive_maps: &[&TextureView],
+    ) -> wgpu::BindGroup {
+        let normal_maps = match normal_maps {
+            [] => fill_texture_array(&[&self.flat.view]),
+            maps => fill_texture_array(maps),
+        };
+        let emissive_maps = match emissive_maps {
+            [] => fill_texture_array(&[&self.dark.view]),
+            maps => fill_texture_array(maps),
+        };
+
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureViewArray(&views),
+                    resource: wgpu::BindingResource::TextureViewArray(&normal_maps),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
+                    resource: wgpu::BindingResource::TextureViewArray(&emissive_maps),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
                     resource: wgpu::BindingResource::Sampler(&ArrayTexture::create_sampler(device)),
                 },
             ],
             label: None,
-        });
-
-        Self {
-            vertex_buffer,
-            index_buffer,
-            instance_buffer,
-            bind_group,
-            num_indices: index_data.len() as u32,
-            id: asset.id.to_string(),
-        }
-    }
-
-    pub fn update_instance_buffer(&mut self, instances: Vec<InstanceRaw>, queue: &wgpu::Queue) {
-        queue.write_buffer(
-            &self.instance_buffer,
-            0,
-            bytemuck::cast_slice(instances.as_slice()),
-        );
+        })
     }
 }
 
@@ -145,12 +342,66 @@ where
     ) {
         self.set_vertex_buffer(0, model.vertex_buffer.slice(..));
         self.set_vertex_buffer(1, model.instance_buffer.slice(..));
-        self.set_index_buffer(model.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        self.set_index_buffer(model.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
         self.set_bind_group(0, uniform_bind_group, &[]);
         self.set_bind_group(1, &model.bind_group, &[]);
+        self.set_bind_group(2, &model.maps_bind_group, &[]);
         self.draw_indexed(0..model.num_indices, 0, instances);
     }
 }
+/// Cuts `rect` (min x, min y, max x, max y, y down) into the patches of a nine-slice of an
+/// image `size` pixels big, each with the uvs it samples. Borders are `scale` times their size in
+/// the image, squeezed to fit if `rect` is too small for them.
+pub(crate) fn nine_slice(
+    rect: [f32; 4],
+    (width, height): (u32, u32),
+    insets: &Insets,
+    scale: f32,
+) -> Vec<([f32; 4], [f32; 4])> {
+    let [x0, y0, x1, y1] = rect;
+    let (left, right) = fit_borders(insets.left, insets.right, scale, x1 - x0);
+    let (top, bottom) = fit_borders(insets.top, insets.bottom, scale, y1 - y0);
+    let xs = [x0, x0 + left, x1 - right, x1];
+    let ys = [y0, y0 + top, y1 - bottom, y1];
+    let us = [
+        0.0,
+        insets.left as f32 / width as f32,
+        1.0 - insets.right as f32 / width as f32,
+        1.0,
+    ];
+    let vs = [
+        0.0,
+        insets.top as f32 / height as f32,
+        1.0 - insets.bottom as f32 / height as f32,
+        1.0,
+    ];
+
+    let mut patches = vec![];
+    for row in 0..3 {
+        for column in 0..3 {
+            if xs[column + 1] <= xs[column] || ys[row + 1] <= ys[row] {
+                continue;
+            }
+            patches.push((
+                [xs[column], ys[row], xs[column + 1], ys[row + 1]],
+                [us[column], vs[row], us[column + 1], vs[row + 1]],
+            ));
+        }
+    }
+    patches
+}
+
+// Scales a pair of opposite borders, shrinking both evenly if they don't fit in `length`.
+fn fit_borders(start: u32, end: u32, scale: f32, length: f32) -> (f32, f32) {
+    let (start, end) = (start as f32 * scale, end as f32 * scale);
+    if start + end > length && start + end > 0.0 {
+        let fit = length.max(0.0) / (start + end);
+        (start * fit, end * fit)
+    } else {
+        (start, end)
+    }
+}
+
 fn create_vertices(width: u32, height: u32, pixel_per_metre: u32) -> (Vec<Vertex>, Vec<Index>) {
     let w = (width as f32 / pixel_per_metre as f32) / 2.0;
     let h = (height as f32 / pixel_per_metre as f32) / 2.0;
@@ -173,7 +424,77 @@ fn create_vertices(width: u32, height: u32, pixel_per_metre: u32) -> (Vec<Vertex
         },
     ];
 
-    let index_data: &[u16] = &[0, 1, 2, 2, 3, 0];
+    let index_data: &[Index] = &[0, 1, 2, 2, 3, 0];
 
     (vertex_data.to_vec(), index_data.to_vec())
 }
+
+// One quad per tile, the grid centred on the origin like a sprite's quad. `tileset` is the size
+// of the tileset image in pixels.
+fn create_tile_vertices(
+    grid: &TileGrid,
+    tileset: (u32, u32),
+    pixel_per_metre: u32,
+) -> (Vec<Vertex>, Vec<Index>) {
+    let (tile_w, tile_h) = grid.tile_size;
+    let ppm = pixel_per_metre as f32;
+    let w = tile_w as f32 / ppm;
+    let h = tile_h as f32 / ppm;
+    let left = -w * grid.width as f32 / 2.0;
+    let top = h * grid.height as f32 / 2.0;
+
+    let mut vertex_data = vec![];
+    let mut index_data = vec![];
+
+    for (i, tile) in grid.tiles.iter().enumerate() {
+        let tile = match tile {
+            Some(tile) => tile,
+            None => continue,
+        };
+        let column = i as u32 % grid.width;
+        let row = i as u32 / grid.width;
+        let x0 = left + column as f32 * w;
+        let y1 = top - row as f32 * h;
+        let (x1, y0) = (x0 + w, y1 - h);
+
+        let u = grid.margin + (tile.index % grid.columns) * (tile_w + grid.spacing);
+        let v = grid.margin + (tile.index / grid.columns) * (tile_h + grid.spacing);
+        let mut u0 = u as f32 / tileset.0 as f32;
+        let mut v0 = v as f32 / tileset.1 as f32;
+        let mut u1 = (u + tile_w) as f32 / tileset.0 as f32;
+        let mut v1 = (v + tile_h) as f32 / tileset.1 as f32;
+        if tile.flip_x {
+            std::mem::swap(&mut u0, &mut u1);
+        }
+        if tile.flip_y {
+            std::mem::swap(&mut v0, &mut v1);
+        }
+
+        let first = vertex_data.len() as Index;
+        vertex_data.extend_from_slice(&[
+            Vertex {
+                pos: [x0, y0, 0.0, 1.0],
+                tex_coord: [u0, v1],
+            },
+            Vertex {
+                pos: [x1, y0, 0.0, 1.0],
+                tex_coord: [u1, v1],
+            },
+            Vertex {
+                pos: [x1, y1, 0.0, 1.0],
+                tex_coord: [u1, v0],
+            },
+            Vertex {
+                pos: [x0, y1, 0.0, 1.0],
+                tex_coord: [u0, v0],
+            },
+        ]);
+        index_data.extend(
+            [0, 1, 2, 2, 3, 0]
+                .iter()
+                .map(|offset| first + offset),
+        );
+    }
+
+    (vertex_data, index_data)
+}
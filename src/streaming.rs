@@ -0,0 +1,235 @@
+use crate::{
+    asset::{LoadError, SpriteAsset},
+    camera::ParallaxCamera,
+    scene::AssetChange,
+    Position, Rect, Rotation, Scale, Sprite,
+};
+use glam::{Quat, Vec2, Vec3};
+use hecs::{Entity, World};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+/// Where a chunk's scene file is and what part of the world it covers, so chunks can be picked
+/// without reading them.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChunkEntry {
+    pub scene: PathBuf,
+    /// x/y bounds of everything in the chunk, including the size of its sprites.
+    pub bounds: Rect,
+    /// Nearest and furthest z of the chunk's entities.
+    pub depth: (f32, f32),
+}
+
+/// The list of chunks a level is split into.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WorldManifest {
+    pub chunks: Vec<ChunkEntry>,
+}
+
+/// The contents of a chunk's scene file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChunkScene {
+    /// Sprite assets the chunk's entities use. Assets shared with other chunks, or registered
+    /// up front with the app, are only loaded once.
+    #[serde(default)]
+    pub sprites: Vec<SpriteAsset>,
+    pub entities: Vec<ChunkEntity>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChunkEntity {
+    pub sprite: String,
+    pub position: Vec3,
+    /// Radians about the y axis.
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "default_scale")]
+    pub scale: u8,
+}
+
+fn default_scale() -> u8 {
+    1
+}
+
+impl WorldManifest {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let src = read_to_string(path)?;
+        ron::de::from_str(&src).map_err(|err| LoadError::Parse(err.to_string()))
+    }
+}
+
+impl ChunkScene {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let src = read_to_string(path)?;
+        ron::de::from_str(&src).map_err(|err| LoadError::Parse(err.to_string()))
+    }
+}
+
+/// Loads chunks of a level, and the sprite assets they need, as they come near what the active
+/// cameras can see and unloads them once they are well out of sight. How far a camera sees is
+/// worked out at each chunk's depth, so deep background chunks stay loaded across a much wider
+/// range of camera positions than the gameplay layer.
+///
+/// Hand it to [`crate::Game::stream_world`]; it runs after the systems each frame.
+pub struct ChunkStreamer {
+    pub manifest: WorldManifest,
+    /// Metres beyond the visible region at which chunks are loaded.
+    pub load_margin: f32,
+    /// Metres beyond the visible region at which chunks are unloaded. Larger than
+    /// `load_margin` so a camera sitting on a chunk edge doesn't reload it every frame.
+    pub unload_margin: f32,
+    loaded: HashMap<usize, Vec<Entity>>,
+    // Chunk scenes read from disk are kept so reloading one doesn't parse it again.
+    scenes: HashMap<usize, ChunkScene>,
+    // How many loaded chunks use each streamed sprite asset.
+    asset_users: HashMap<String, usize>,
+}
+
+impl ChunkStreamer {
+    pub fn new(manifest: WorldManifest) -> Self {
+        ChunkStreamer {
+            manifest,
+            load_margin: 4.0,
+            unload_margin: 8.0,
+            loaded: HashMap::new(),
+            scenes: HashMap::new(),
+            asset_users: HashMap::new(),
+        }
+    }
+    pub fn with_margins(mut self, load_margin: f32, unload_margin: f32) -> Self {
+        self.load_margin = load_margin;
+        self.unload_margin = unload_margin.max(load_margin);
+        self
+    }
+    pub fn is_loaded(&self, chunk: usize) -> bool {
+        self.loaded.contains_key(&chunk)
+    }
+
+    /// Loads and unloads chunks for the current camera positions. `sprite_sizes` holds every
+    /// sprite asset the renderer knows about; streamed assets are added to and removed from it
+    /// and the renderer is told through `changes`.
+    pub(crate) fn update(
+        &mut self,
+        world: &mut World,
+        cameras: &[ParallaxCamera],
+        sprite_sizes: &mut HashMap<String, (u32, u32)>,
+        changes: &mut Vec<AssetChange>,
+    ) {
+        let mut unload = vec![];
+        let mut load = vec![];
+        for (i, chunk) in self.manifest.chunks.iter().enumerate() {
+            if self.loaded.contains_key(&i) {
+                if !is_near(chunk, cameras, self.unload_margin) {
+                    unload.push(i);
+                }
+            } else if is_near(chunk, cameras, self.load_margin) {
+                load.push(i);
+            }
+        }
+
+        // Unload first so an asset moving between chunks is released before it is needed.
+        for i in unload {
+            for entity in self.loaded.remove(&i).unwrap_or_default() {
+                // Gameplay may already have despawned it.
+                let _ = world.despawn(entity);
+            }
+            let streamed: HashSet<String> = match self.scenes.get(&i) {
+                Some(scene) => scene.sprites.iter().map(|asset| asset.id.clone()).collect(),
+                None => continue,
+            };
+            for id in streamed {
+                self.release(&id, sprite_sizes, changes);
+            }
+        }
+
+        for i in load {
+            if !self.scenes.contains_key(&i) {
+                let path = &self.manifest.chunks[i].scene;
+                match ChunkScene::load(path) {
+                    Ok(scene) => {
+                        self.scenes.insert(i, scene);
+                    }
+                    Err(err) => {
+                        log::error!("could not load chunk {:?}: {}", path, err);
+                        // Mark it loaded so a broken file isn't read every frame.
+                        self.loaded.insert(i, vec![]);
+                        continue;
+                    }
+                }
+            }
+            let scene = &self.scenes[&i];
+
+            let mut acquired = HashSet::new();
+            for asset in scene.sprites.iter() {
+                if !acquired.insert(asset.id.clone()) {
+                    continue;
+                }
+                match self.asset_users.get_mut(&asset.id) {
+                    Some(users) => *users += 1,
+                    // Registered up front, the app owns it.
+                    None if sprite_sizes.contains_key(&asset.id) => {}
+                    None => match asset.try_size() {
+                        Ok(size) => {
+                            self.asset_users.insert(asset.id.clone(), 1);
+                            sprite_sizes.insert(asset.id.clone(), size);
+                            changes.push(AssetChange::Load(asset.clone()));
+                        }
+                        Err(err) => log::error!("could not load sprite {:?}: {}", asset.id, err),
+                    },
+                }
+            }
+
+            // Entities whose sprite didn't load are left out rather than drawn with nothing.
+            let entities = scene
+                .entities
+                .iter()
+                .filter(|entity| {
+                    let loaded = sprite_sizes.contains_key(&entity.sprite);
+                    if !loaded {
+                        log::warn!("skipping entity with unloaded sprite {:?}", entity.sprite);
+                    }
+                    loaded
+                })
+                .map(|entity| {
+                    world.spawn((
+                        Position(entity.position),
+                        Rotation(Quat::from_rotation_y(entity.rotation)),
+                        Scale(entity.scale),
+                        Sprite::new(&entity.sprite),
+                    ))
+                })
+                .collect();
+            self.loaded.insert(i, entities);
+        }
+    }
+
+    fn release(
+        &mut self,
+        id: &str,
+        sprite_sizes: &mut HashMap<String, (u32, u32)>,
+        changes: &mut Vec<AssetChange>,
+    ) {
+        if let Some(users) = self.asset_users.get_mut(id) {
+            *users -= 1;
+            if *users == 0 {
+                self.asset_users.remove(id);
+                sprite_sizes.remove(id);
+                changes.push(AssetChange::Unload(id.to_string()));
+            }
+        }
+    }
+}
+
+// Whether any camera can see within `margin` of the chunk at either end of its depth range.
+// The visible region only grows with depth, so everything in between is covered too.
+fn is_near(chunk: &ChunkEntry, cameras: &[ParallaxCamera], margin: f32) -> bool {
+    cameras.iter().any(|cam| {
+        let near = cam.visible_region(chunk.depth.0 - cam.eye.z, Vec2::zero());
+        let far = cam.visible_region(chunk.depth.1 - cam.eye.z, Vec2::zero());
+        near.union(&far).expand(margin).intersects(&chunk.bounds)
+    })
+}
@@ -0,0 +1,541 @@
+use crate::asset::{FontAsset, FontSource, LoadError};
+use glam::Vec2;
+use image::RgbaImage;
+use std::{collections::HashMap, fs::read_to_string, path::Path};
+
+/// Text drawn with a font loaded through [`crate::Game::load_font`]. World space text needs a
+/// `Position`, which is the top of the first line at the point given by `align`; it gets the
+/// same parallax as a sprite at that depth.
+#[derive(Clone, Debug)]
+pub struct Text {
+    pub font: String,
+    pub spans: Vec<TextSpan>,
+    pub align: TextAlign,
+    /// Width in font pixels at which lines wrap between words.
+    pub wrap_width: Option<f32>,
+    /// Screen pixels, or in world space sprite pixels, per font pixel.
+    pub scale: f32,
+    pub space: TextSpace,
+}
+
+/// A run of text in one colour.
+#[derive(Clone, Debug)]
+pub struct TextSpan {
+    pub text: String,
+    /// Linear RGBA, multiplied with the glyph.
+    pub colour: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextAlign {
+    Left,
+    Centre,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextSpace {
+    World,
+    /// Drawn over everything on the window at this many pixels from the top left, on UI layer
+    /// 0. Text with a [`crate::ui::UiNode`] is laid out in the node instead, in either space.
+    Screen(Vec2),
+}
+
+pub const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+impl Text {
+    pub fn new(font: &str, text: &str) -> Self {
+        Text {
+            font: font.to_string(),
+            spans: vec![TextSpan {
+                text: text.to_string(),
+                colour: WHITE,
+            }],
+            align: TextAlign::Left,
+            wrap_width: None,
+            scale: 1.0,
+            space: TextSpace::World,
+        }
+    }
+    pub fn on_screen(mut self, position: Vec2) -> Self {
+        self.space = TextSpace::Screen(position);
+        self
+    }
+    pub fn with_align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+    pub fn with_wrap_width(mut self, width: f32) -> Self {
+        self.wrap_width = Some(width);
+        self
+    }
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+    /// Colours all of the text.
+    pub fn with_colour(mut self, colour: [f32; 4]) -> Self {
+        for span in self.spans.iter_mut() {
+            span.colour = colour;
+        }
+        self
+    }
+    /// Appends text in another colour.
+    pub fn push_span(&mut self, text: &str, colour: [f32; 4]) {
+        self.spans.push(TextSpan {
+            text: text.to_string(),
+            colour,
+        });
+    }
+    /// Replaces all the text with a single span, keeping the first span's colour.
+    pub fn set_text(&mut self, text: &str) {
+        let colour = self.spans.first().map_or(WHITE, |span| span.colour);
+        self.spans = vec![TextSpan {
+            text: text.to_string(),
+            colour,
+        }];
+    }
+}
+
+/// Where a glyph is in its font's pages, and how to place it. Sizes are in font pixels.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Glyph {
+    pub page: u32,
+    /// Top left and bottom right in uv coordinates.
+    pub uv: [f32; 4],
+    pub size: Vec2,
+    /// From the pen position on the top of the line to the top left of the glyph.
+    pub offset: Vec2,
+    pub advance: f32,
+}
+
+pub(crate) struct FontMetrics {
+    pub glyphs: HashMap<char, Glyph>,
+    pub line_height: f32,
+    pub kerning: HashMap<(char, char), f32>,
+}
+
+/// A glyph placed by [`layout`], in font pixels from the anchor, y down.
+pub(crate) struct PlacedGlyph {
+    pub glyph: Glyph,
+    pub position: Vec2,
+    pub colour: [f32; 4],
+}
+
+/// Reads a font's metrics and the images of its pages.
+pub(crate) fn load_font(asset: &FontAsset) -> Result<(FontMetrics, Vec<RgbaImage>), LoadError> {
+    match &asset.source {
+        FontSource::BMFont(path) => load_bmfont(path),
+        FontSource::Ttf { path, size } => rasterise_ttf(path, *size),
+    }
+}
+
+fn load_bmfont(path: &Path) -> Result<(FontMetrics, Vec<RgbaImage>), LoadError> {
+    let src = read_to_string(path)?;
+    let parse_error = |what: &str| LoadError::Parse(format!("{:?}: {}", path, what));
+
+    let mut line_height = None;
+    let mut scale = (1.0, 1.0);
+    let mut page_files = vec![];
+    let mut glyphs = HashMap::new();
+    let mut kerning = HashMap::new();
+
+    for line in src.lines() {
+        let tag = match line.split_whitespace().next() {
+            Some(tag) => tag,
+            None => continue,
+        };
+        let fields = bmfont_fields(line);
+        let get = |key: &str| -> Result<f32, LoadError> {
+            fields
+                .get(key)
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| parse_error(&format!("{} has no {}", tag, key)))
+        };
+        match tag {
+            "common" => {
+                line_height = Some(get("lineHeight")?);
+                scale = (get("scaleW")?, get("scaleH")?);
+            }
+            "page" => {
+                let id = get("id")? as usize;
+                let file = fields
+                    .get("file")
+                    .ok_or_else(|| parse_error("page has no file"))?;
+                if page_files.len() <= id {
+                    page_files.resize(id + 1, String::new());
+                }
+                page_files[id] = file.clone();
+            }
+            "char" => {
+                let ch = match std::char::from_u32(get("id")? as u32) {
+                    Some(ch) => ch,
+                    None => continue,
+                };
+                let (x, y) = (get("x")?, get("y")?);
+                let size = Vec2::new(get("width")?, get("height")?);
+                glyphs.insert(
+                    ch,
+                    Glyph {
+                        page: get("page")? as u32,
+                        uv: [
+                            x / scale.0,
+                            y / scale.1,
+                            (x + size.x) / scale.0,
+                            (y + size.y) / scale.1,
+                        ],
+                        size,
+                        offset: Vec2::new(get("xoffset")?, get("yoffset")?),
+                        advance: get("xadvance")?,
+                    },
+                );
+            }
+            "kerning" => {
+                let first = std::char::from_u32(get("first")? as u32);
+                let second = std::char::from_u32(get("second")? as u32);
+                if let (Some(first), Some(second)) = (first, second) {
+                    kerning.insert((first, second), get("amount")?);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let line_height = line_height.ok_or_else(|| {
+        parse_error("not a text format BMFont file, export with the text descriptor format")
+    })?;
+    let pages = page_files
+        .iter()
+        .map(|file| {
+            let page = path.parent().unwrap_or_else(|| Path::new("")).join(file);
+            image::open(&page)
+                .map(|image| image.into_rgba8())
+                .map_err(|err| parse_error(&format!("page {:?}: {}", page, err)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((
+        FontMetrics {
+            glyphs,
+            line_height,
+            kerning,
+        },
+        pages,
+    ))
+}
+
+// The `key=value` pairs of a line of a BMFont file, values possibly quoted.
+fn bmfont_fields(line: &str) -> HashMap<&str, String> {
+    let mut fields = HashMap::new();
+    let mut rest = line;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].rsplit(' ').next().unwrap_or("").trim();
+        let after = &rest[eq + 1..];
+        let (value, next) = if let Some(quoted) = after.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            (&quoted[..end], &quoted[(end + 1).min(quoted.len())..])
+        } else {
+            let end = after.find(' ').unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+        fields.insert(key, value.to_string());
+        rest = next;
+    }
+    fields
+}
+
+// Latin-1 is rasterised up front, other characters draw as '?'.
+fn rasterise_ttf(path: &Path, size: f32) -> Result<(FontMetrics, Vec<RgbaImage>), LoadError> {
+    const ATLAS_WIDTH: u32 = 512;
+    const PADDING: u32 = 1;
+
+    let bytes = std::fs::read(path)?;
+    let font = fontdue::Font::from_bytes(bytes, fontdue::FontSettings::default())
+        .map_err(|err| LoadError::Parse(format!("{:?}: {}", path, err)))?;
+    let line = font
+        .horizontal_line_metrics(size)
+        .ok_or_else(|| LoadError::Parse(format!("{:?} has no horizontal metrics", path)))?;
+
+    let chars = (' '..='~').chain('\u{a0}'..='\u{ff}');
+    let rasterised: Vec<_> = chars.map(|ch| (ch, font.rasterize(ch, size))).collect();
+
+    // Pack into rows from the top left.
+    let mut placements = vec![];
+    let (mut x, mut y, mut row_height) = (PADDING, PADDING, 0);
+    for (_, (metrics, _)) in rasterised.iter() {
+        let (w, h) = (metrics.width as u32, metrics.height as u32);
+        if x + w + PADDING > ATLAS_WIDTH {
+            x = PADDING;
+            y += row_height + PADDING;
+            row_height = 0;
+        }
+        placements.push((x, y));
+        x += w + PADDING;
+        row_height = row_height.max(h);
+    }
+    let atlas_height = (y + row_height + PADDING).next_power_of_two();
+
+    let mut atlas = RgbaImage::new(ATLAS_WIDTH, atlas_height);
+    let mut glyphs = HashMap::new();
+    for ((ch, (metrics, coverage)), (gx, gy)) in rasterised.iter().zip(placements) {
+        for (i, &alpha) in coverage.iter().enumerate() {
+            let px = gx + (i % metrics.width) as u32;
+            let py = gy + (i / metrics.width) as u32;
+            atlas.put_pixel(px, py, image::Rgba([255, 255, 255, alpha]));
+        }
+        let size = Vec2::new(metrics.width as f32, metrics.height as f32);
+        let (w, h) = (ATLAS_WIDTH as f32, atlas_height as f32);
+        glyphs.insert(
+            *ch,
+            Glyph {
+                page: 0,
+                uv: [
+                    gx as f32 / w,
+                    gy as f32 / h,
+                    (gx as f32 + size.x) / w,
+                    (gy as f32 + size.y) / h,
+                ],
+                size,
+                // fontdue measures from the baseline, y up.
+                offset: Vec2::new(
+                    metrics.xmin as f32,
+                    line.ascent - (metrics.ymin as f32 + size.y),
+                ),
+                advance: metrics.advance_width,
+            },
+        );
+    }
+
+    Ok((
+        FontMetrics {
+            glyphs,
+            line_height: line.new_line_size,
+            kerning: HashMap::new(),
+        },
+        vec![atlas],
+    ))
+}
+
+/// Places the glyphs of `text` in lines, wrapping and aligning them.
+pub(crate) fn layout(metrics: &FontMetrics, text: &Text) -> Vec<PlacedGlyph> {
+    let mut lines: Vec<Vec<PlacedGlyph>> = vec![vec![]];
+    let mut pen = 0.0;
+    let mut previous = None;
+    // Index in the last line of the first glyph of the word being written.
+    let mut word_start = 0;
+
+    let chars = text
+        .spans
+        .iter()
+        .flat_map(|span| span.text.chars().map(move |ch| (ch, span.colour)));
+
+    for (ch, colour) in chars {
+        if ch == '\n' {
+            lines.push(vec![]);
+            pen = 0.0;
+            previous = None;
+            word_start = 0;
+            continue;
+        }
+        let glyph = match metrics.glyphs.get(&ch).or_else(|| metrics.glyphs.get(&'?')) {
+            Some(glyph) => *glyph,
+            None => continue,
+        };
+        if let Some(previous) = previous {
+            pen += metrics.kerning.get(&(previous, ch)).copied().unwrap_or(0.0);
+        }
+        previous = Some(ch);
+
+        let line = lines.last_mut().expect("there is always a line");
+        // Spaces only move the pen, ending the word.
+        if ch == ' ' {
+            pen += glyph.advance;
+            word_start = line.len();
+            continue;
+        }
+
+        if let Some(wrap_width) = text.wrap_width {
+            if !line.is_empty() && pen + glyph.advance > wrap_width {
+                // Move the word being written to a new line, or break it if it is the only one.
+                let split = if word_start > 0 {
+                    word_start
+                } else {
+                    line.len()
+                };
+                let mut word = line.split_off(split);
+                let start = word.first().map_or(pen, |placed| placed.position.x);
+                for placed in word.iter_mut() {
+                    placed.position.x -= start;
+                }
+                pen -= start;
+                lines.push(word);
+                word_start = 0;
+            }
+        }
+
+        let line = lines.last_mut().expect("there is always a line");
+        line.push(PlacedGlyph {
+            glyph,
+            position: Vec2::new(pen, 0.0),
+            colour,
+        });
+        pen += glyph.advance;
+    }
+
+    let mut placed = vec![];
+    for (row, line) in lines.into_iter().enumerate() {
+        let width = line
+            .last()
+            .map_or(0.0, |last| last.position.x + last.glyph.advance);
+        let start = match text.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Centre => -width / 2.0,
+            TextAlign::Right => -width,
+        };
+        let top = row as f32 * metrics.line_height;
+        for mut glyph in line {
+            glyph.position = Vec2::new(start, top) + glyph.position + glyph.glyph.offset;
+            placed.push(glyph);
+        }
+    }
+    placed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each glyph's page is its character, so placed glyphs can be told apart.
+    fn metrics(with_fallback: bool) -> FontMetrics {
+        let mut glyphs = HashMap::new();
+        let mut chars = vec![('a', 10.0), ('b', 10.0), ('W', 20.0), (' ', 5.0)];
+        if with_fallback {
+            chars.push(('?', 8.0));
+        }
+        for (ch, advance) in chars {
+            glyphs.insert(
+                ch,
+                Glyph {
+                    page: ch as u32,
+                    uv: [0.0; 4],
+                    size: Vec2::new(advance, 16.0),
+                    offset: if ch == 'W' {
+                        Vec2::new(1.0, 3.0)
+                    } else {
+                        Vec2::zero()
+                    },
+                    advance,
+                },
+            );
+        }
+        let mut kerning = HashMap::new();
+        kerning.insert(('a', 'b'), -2.0);
+        FontMetrics {
+            glyphs,
+            line_height: 16.0,
+            kerning,
+        }
+    }
+
+    fn placed(text: &Text, with_fallback: bool) -> Vec<(char, f32, f32)> {
+        layout(&metrics(with_fallback), text)
+            .iter()
+            .map(|placed| {
+                let ch = std::char::from_u32(placed.glyph.page).expect("pages are characters");
+                (ch, placed.position.x, placed.position.y)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn kerning_moves_the_next_glyph() {
+        let text = Text::new("font", "ab");
+        assert_eq!(placed(&text, true), vec![('a', 0.0, 0.0), ('b', 8.0, 0.0)]);
+    }
+
+    #[test]
+    fn wraps_between_words() {
+        let text = Text::new("font", "aa aa").with_wrap_width(30.0);
+        assert_eq!(
+            placed(&text, true),
+            vec![
+                ('a', 0.0, 0.0),
+                ('a', 10.0, 0.0),
+                ('a', 0.0, 16.0),
+                ('a', 10.0, 16.0)
+            ]
+        );
+
+        // The word being written moves down with the glyph that didn't fit.
+        let text = Text::new("font", "b aaa").with_wrap_width(30.0);
+        assert_eq!(
+            placed(&text, true),
+            vec![
+                ('b', 0.0, 0.0),
+                ('a', 0.0, 16.0),
+                ('a', 10.0, 16.0),
+                ('a', 20.0, 16.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn breaks_a_word_too_long_for_a_line() {
+        let text = Text::new("font", "aaaa").with_wrap_width(25.0);
+        assert_eq!(
+            placed(&text, true),
+            vec![
+                ('a', 0.0, 0.0),
+                ('a', 10.0, 0.0),
+                ('a', 0.0, 16.0),
+                ('a', 10.0, 16.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn newlines_start_a_new_line_without_kerning() {
+        let text = Text::new("font", "a\nb");
+        assert_eq!(placed(&text, true), vec![('a', 0.0, 0.0), ('b', 0.0, 16.0)]);
+    }
+
+    #[test]
+    fn lines_are_aligned_on_their_own_width() {
+        let text = Text::new("font", "ab\nW").with_align(TextAlign::Centre);
+        assert_eq!(
+            placed(&text, true),
+            vec![('a', -9.0, 0.0), ('b', -1.0, 0.0), ('W', -9.0, 19.0)]
+        );
+
+        // Trailing spaces don't count towards the width.
+        let text = Text::new("font", "ab \nW").with_align(TextAlign::Right);
+        assert_eq!(
+            placed(&text, true),
+            vec![('a', -18.0, 0.0), ('b', -10.0, 0.0), ('W', -19.0, 19.0)]
+        );
+    }
+
+    #[test]
+    fn missing_characters_draw_as_question_marks() {
+        let text = Text::new("font", "aéb");
+        assert_eq!(
+            placed(&text, true),
+            vec![('a', 0.0, 0.0), ('?', 10.0, 0.0), ('b', 18.0, 0.0)]
+        );
+        // Or not at all without one.
+        assert_eq!(placed(&text, false), vec![('a', 0.0, 0.0), ('b', 8.0, 0.0)]);
+    }
+
+    #[test]
+    fn bmfont_fields_keep_spaces_in_quoted_values() {
+        let fields = bmfont_fields(r#"info face="Open Sans Bold" size=32 bold=1 charset="""#);
+        assert_eq!(fields["face"], "Open Sans Bold");
+        assert_eq!(fields["size"], "32");
+        assert_eq!(fields["bold"], "1");
+        assert_eq!(fields["charset"], "");
+
+        let fields = bmfont_fields(r#"page id=0 file="font 0.png""#);
+        assert_eq!(fields["id"], "0");
+        assert_eq!(fields["file"], "font 0.png");
+    }
+}
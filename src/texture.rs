@@ -9,10 +9,10 @@ pub struct DepthTexture {
 impl DepthTexture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    pub fn new(device: &wgpu::Device, sc_desc: &wgpu::SwapChainDescriptor) -> Self {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
         let size = wgpu::Extent3d {
-            width: sc_desc.width,
-            height: sc_desc.height,
+            width,
+            height,
             depth: 1,
         };
         let desc = wgpu::TextureDescriptor {
@@ -54,10 +54,24 @@ impl DepthTexture {
 pub struct ArrayTexture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
+    pub size: (u32, u32),
 }
 
 impl ArrayTexture {
     pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, image: RgbaImage) -> Self {
+        Self::with_format(device, queue, image, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+    /// For images that hold data rather than colours, such as normal maps, which must be
+    /// sampled as they are.
+    pub fn linear(device: &wgpu::Device, queue: &wgpu::Queue, image: RgbaImage) -> Self {
+        Self::with_format(device, queue, image, wgpu::TextureFormat::Rgba8Unorm)
+    }
+    fn with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: RgbaImage,
+        format: wgpu::TextureFormat,
+    ) -> Self {
         let texels = image.to_vec();
         let texture_extent = wgpu::Extent3d {
             width: image.width(),
@@ -70,7 +84,7 @@ impl ArrayTexture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format,
             usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
         };
 
@@ -93,7 +107,11 @@ impl ArrayTexture {
             texture_extent,
         );
 
-        Self { texture, view }
+        Self {
+            texture,
+            view,
+            size: (texture_extent.width, texture_extent.height),
+        }
     }
     pub fn create_sampler(device: &wgpu::Device) -> wgpu::Sampler {
         device.create_sampler(&wgpu::SamplerDescriptor {
@@ -108,3 +126,42 @@ impl ArrayTexture {
         })
     }
 }
+
+/// A colour and depth target a camera renders into, which sprites can then sample.
+pub struct RenderTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub depth: DepthTexture,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderTexture {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        let desc = wgpu::TextureDescriptor {
+            label: Some("Render Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        };
+
+        let texture = device.create_texture(&desc);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth = DepthTexture::new(device, width, height);
+
+        Self {
+            texture,
+            view,
+            depth,
+            width,
+            height,
+        }
+    }
+}
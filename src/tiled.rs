@@ -0,0 +1,1089 @@
+//! Imports maps made with the [Tiled](https://www.mapeditor.org) editor, saved as either `.tmx`
+//! or `.tmj`. Each tile layer becomes a tilemap sprite at the depth given by the layer's custom
+//! `depth` property, and object layers become [`TiledObject`]s at their layer's depth.
+//!
+//! Only finite, orthogonal maps with CSV layer data are supported, and tilesets must use the
+//! map's tile size.
+
+use crate::{
+    asset::{LoadError, SpriteAsset, Tile, TileGrid},
+    sprite::PIXELS_PER_METRE,
+    Game, Position, Rotation, Scale, Sprite,
+};
+use glam::{Quat, Vec2, Vec3};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+const FLIP_X: u32 = 0x8000_0000;
+const FLIP_Y: u32 = 0x4000_0000;
+const FLIP_DIAGONAL: u32 = 0x2000_0000;
+// Only meaningful on hexagonal maps, but Tiled can still save it on orthogonal ones.
+const ROTATE_HEXAGONAL: u32 = 0x1000_0000;
+const FLAGS: u32 = FLIP_X | FLIP_Y | FLIP_DIAGONAL | ROTATE_HEXAGONAL;
+
+/// One tile layer of a map, or the part of it drawn from one tileset when it uses several.
+#[derive(Clone, Debug)]
+pub struct TiledLayer {
+    pub name: String,
+    pub asset: SpriteAsset,
+    /// Centre of the layer in metres from the map's top left corner, z being its depth.
+    pub position: Vec3,
+}
+
+/// An object placed in an object layer.
+#[derive(Clone, Debug)]
+pub struct TiledObject {
+    pub name: String,
+    /// The object's class, called type before Tiled 1.9.
+    pub class: String,
+    /// Centre of the object in metres from the map's top left corner, z being its layer's depth.
+    pub position: Vec3,
+    /// Size in metres, zero for points.
+    pub size: Vec2,
+    pub properties: HashMap<String, String>,
+}
+
+pub struct TiledMap {
+    pub layers: Vec<TiledLayer>,
+    pub objects: Vec<TiledObject>,
+}
+
+impl TiledMap {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let map = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("tmx") => tmx::load(path)?,
+            Some("tmj") | Some("json") => tmj::load(path)?,
+            _ => return Err(parse_error(format!("{:?} is not a Tiled map", path))),
+        };
+        Self::build(path, map)
+    }
+
+    /// The tilemap assets for the map's layers, to hand to the app with the other sprites.
+    pub fn sprite_assets(&self) -> Vec<SpriteAsset> {
+        self.layers.iter().map(|layer| layer.asset.clone()).collect()
+    }
+
+    /// Spawns the tile layers, and every object with a `sprite` property naming a sprite asset,
+    /// with the map's top left corner at `origin`. Other objects are left for the game to spawn
+    /// from [`Self::objects`].
+    pub fn spawn(&self, game: &mut Game, origin: Vec2) {
+        let origin = Vec3::new(origin.x, origin.y, 0.0);
+        for layer in self.layers.iter() {
+            game.spawn_entity((
+                Position(origin + layer.position),
+                Rotation(Quat::identity()),
+                Scale(1),
+                Sprite::new(&layer.asset.id),
+            ));
+        }
+        for object in self.objects.iter() {
+            if let Some(sprite) = object.properties.get("sprite") {
+                game.spawn_entity((
+                    Position(origin + object.position),
+                    Rotation(Quat::identity()),
+                    Scale(1),
+                    Sprite::new(sprite),
+                ));
+            }
+        }
+    }
+
+    fn build(path: &Path, map: RawMap) -> Result<Self, LoadError> {
+        let ppm = PIXELS_PER_METRE as f32;
+        let map_size = Vec2::new(
+            (map.width * map.tile_size.0) as f32,
+            (map.height * map.tile_size.1) as f32,
+        );
+        let mut layers = vec![];
+        let mut objects = vec![];
+
+        for (index, layer) in map.layers.into_iter().enumerate() {
+            let depth: f32 = match layer.properties.get("depth") {
+                Some(depth) => depth.parse().map_err(|_| {
+                    parse_error(format!("layer {} has a non numeric depth", layer.name))
+                })?,
+                None => {
+                    return Err(parse_error(format!(
+                        "layer {} needs a depth property",
+                        layer.name
+                    )))
+                }
+            };
+
+            match layer.content {
+                LayerContent::Tiles(data) => {
+                    let count = (map.width * map.height) as usize;
+                    if data.len() != count {
+                        return Err(parse_error(format!(
+                            "layer {} has {} tiles, expected {}",
+                            layer.name,
+                            data.len(),
+                            count
+                        )));
+                    }
+
+                    // Each tileset is its own texture, so split the layer by tileset.
+                    let mut grids: BTreeMap<usize, Vec<Option<Tile>>> = BTreeMap::new();
+                    let mut rotated = false;
+                    for (i, &gid) in data.iter().enumerate() {
+                        let id = gid & !FLAGS;
+                        if id == 0 {
+                            continue;
+                        }
+                        rotated |= gid & (FLIP_DIAGONAL | ROTATE_HEXAGONAL) != 0;
+                        let tileset = map
+                            .tilesets
+                            .iter()
+                            .rposition(|tileset| tileset.first_gid <= id)
+                            .ok_or_else(|| parse_error(format!("tile {} has no tileset", id)))?;
+                        grids.entry(tileset).or_insert_with(|| vec![None; count])[i] = Some(Tile {
+                            index: id - map.tilesets[tileset].first_gid,
+                            flip_x: gid & FLIP_X != 0,
+                            flip_y: gid & FLIP_Y != 0,
+                        });
+                    }
+                    if rotated {
+                        log::warn!("layer {} has rotated tiles, drawn unrotated", layer.name);
+                    }
+
+                    let centre = map_size / 2.0 + layer.offset;
+                    for (tileset, tiles) in grids {
+                        let tileset = &map.tilesets[tileset];
+                        if tileset.tile_size != map.tile_size {
+                            return Err(parse_error(format!(
+                                "tileset {:?} doesn't use the map's tile size",
+                                tileset.image
+                            )));
+                        }
+                        let grid = TileGrid {
+                            width: map.width,
+                            height: map.height,
+                            tile_size: tileset.tile_size,
+                            margin: tileset.margin,
+                            spacing: tileset.spacing,
+                            columns: tileset.columns,
+                            tiles,
+                        };
+                        let id = format!("{}#{}:{}", path.display(), index, tileset.first_gid);
+                        layers.push(TiledLayer {
+                            name: layer.name.clone(),
+                            asset: SpriteAsset::tilemap(&id, tileset.image.clone(), grid),
+                            position: Vec3::new(centre.x / ppm, -centre.y / ppm, depth),
+                        });
+                    }
+                }
+                LayerContent::Objects(raw) => {
+                    for object in raw {
+                        // Tile objects are placed by their bottom left corner, others by their
+                        // top left.
+                        let top = match object.gid {
+                            Some(_) => object.position.y - object.size.y,
+                            None => object.position.y,
+                        };
+                        let centre = Vec2::new(object.position.x, top)
+                            + object.size / 2.0
+                            + layer.offset;
+                        objects.push(TiledObject {
+                            name: object.name,
+                            class: object.class,
+                            position: Vec3::new(centre.x / ppm, -centre.y / ppm, depth),
+                            size: object.size / ppm,
+                            properties: object.properties,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(TiledMap { layers, objects })
+    }
+}
+
+// What both file formats are read into. Positions and sizes are in pixels, y down.
+struct RawMap {
+    width: u32,
+    height: u32,
+    tile_size: (u32, u32),
+    // Sorted by first gid.
+    tilesets: Vec<RawTileset>,
+    // Group layers are flattened, their children inheriting offsets and properties.
+    layers: Vec<RawLayer>,
+}
+
+struct RawTileset {
+    first_gid: u32,
+    image: PathBuf,
+    tile_size: (u32, u32),
+    margin: u32,
+    spacing: u32,
+    columns: u32,
+}
+
+struct RawLayer {
+    name: String,
+    offset: Vec2,
+    properties: HashMap<String, String>,
+    content: LayerContent,
+}
+
+enum LayerContent {
+    Tiles(Vec<u32>),
+    Objects(Vec<RawObject>),
+}
+
+struct RawObject {
+    name: String,
+    class: String,
+    position: Vec2,
+    size: Vec2,
+    gid: Option<u32>,
+    properties: HashMap<String, String>,
+}
+
+fn parse_error(msg: String) -> LoadError {
+    LoadError::Parse(msg)
+}
+
+fn check_tileset(tileset: RawTileset) -> Result<RawTileset, LoadError> {
+    if tileset.columns == 0 {
+        return Err(parse_error(format!(
+            "tileset {:?} has no columns",
+            tileset.image
+        )));
+    }
+    Ok(tileset)
+}
+
+fn check_map(orientation: &str, infinite: bool) -> Result<(), LoadError> {
+    if orientation != "orthogonal" {
+        return Err(parse_error(format!("{} maps aren't supported", orientation)));
+    }
+    if infinite {
+        return Err(parse_error("infinite maps aren't supported".to_string()));
+    }
+    Ok(())
+}
+
+// Tilesets saved in their own file, which either format can refer to.
+fn load_external_tileset(path: &Path, first_gid: u32) -> Result<RawTileset, LoadError> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("tsx") => tmx::load_tileset(path, first_gid),
+        Some("tsj") | Some("json") => tmj::load_tileset(path, first_gid),
+        _ => Err(parse_error(format!("{:?} is not a Tiled tileset", path))),
+    }
+}
+
+// Paths in Tiled files are relative to the file.
+fn relative_to(file: &Path, path: &str) -> PathBuf {
+    file.parent().unwrap_or_else(|| Path::new("")).join(path)
+}
+
+fn inherit(
+    parent: &HashMap<String, String>,
+    own: HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut properties = parent.clone();
+    properties.extend(own);
+    properties
+}
+
+mod tmj {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::Value;
+    use std::fs::read_to_string;
+
+    #[derive(Deserialize)]
+    struct Map {
+        width: u32,
+        height: u32,
+        tilewidth: u32,
+        tileheight: u32,
+        orientation: String,
+        #[serde(default)]
+        infinite: bool,
+        layers: Vec<Layer>,
+        tilesets: Vec<Value>,
+    }
+
+    #[derive(Deserialize)]
+    struct Layer {
+        #[serde(rename = "type")]
+        kind: String,
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        data: Option<Value>,
+        #[serde(default)]
+        offsetx: f32,
+        #[serde(default)]
+        offsety: f32,
+        #[serde(default)]
+        layers: Vec<Layer>,
+        #[serde(default)]
+        objects: Vec<Object>,
+        #[serde(default)]
+        properties: Vec<Property>,
+    }
+
+    #[derive(Deserialize)]
+    struct Object {
+        #[serde(default)]
+        name: String,
+        #[serde(default, rename = "type")]
+        kind: String,
+        #[serde(default)]
+        class: String,
+        x: f32,
+        y: f32,
+        #[serde(default)]
+        width: f32,
+        #[serde(default)]
+        height: f32,
+        #[serde(default)]
+        gid: Option<u32>,
+        #[serde(default)]
+        properties: Vec<Property>,
+    }
+
+    #[derive(Deserialize)]
+    struct Property {
+        name: String,
+        value: Value,
+    }
+
+    #[derive(Deserialize)]
+    struct Tileset {
+        image: String,
+        tilewidth: u32,
+        tileheight: u32,
+        #[serde(default)]
+        margin: u32,
+        #[serde(default)]
+        spacing: u32,
+        columns: u32,
+    }
+
+    fn from_str<T: serde::de::DeserializeOwned>(src: &str) -> Result<T, LoadError> {
+        serde_json::from_str(src).map_err(|err| parse_error(err.to_string()))
+    }
+
+    pub(super) fn load(path: &Path) -> Result<RawMap, LoadError> {
+        parse(path, &read_to_string(path)?)
+    }
+
+    // `path` is where the map was read from, which its tilesets are relative to.
+    pub(super) fn parse(path: &Path, src: &str) -> Result<RawMap, LoadError> {
+        let map: Map = from_str(src)?;
+        check_map(&map.orientation, map.infinite)?;
+
+        let mut tilesets = vec![];
+        for tileset in map.tilesets {
+            let first_gid = tileset
+                .get("firstgid")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| parse_error("tileset has no firstgid".to_string()))?
+                as u32;
+            match tileset.get("source").and_then(Value::as_str) {
+                Some(source) => {
+                    tilesets.push(load_external_tileset(&relative_to(path, source), first_gid)?)
+                }
+                None => {
+                    let tileset: Tileset = serde_json::from_value(tileset)
+                        .map_err(|err| parse_error(err.to_string()))?;
+                    tilesets.push(raw_tileset(path, tileset, first_gid)?);
+                }
+            }
+        }
+        tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+        let mut layers = vec![];
+        flatten(map.layers, Vec2::zero(), &HashMap::new(), &mut layers)?;
+
+        Ok(RawMap {
+            width: map.width,
+            height: map.height,
+            tile_size: (map.tilewidth, map.tileheight),
+            tilesets,
+            layers,
+        })
+    }
+
+    pub(super) fn load_tileset(path: &Path, first_gid: u32) -> Result<RawTileset, LoadError> {
+        raw_tileset(path, from_str(&read_to_string(path)?)?, first_gid)
+    }
+
+    fn raw_tileset(path: &Path, tileset: Tileset, first_gid: u32) -> Result<RawTileset, LoadError> {
+        check_tileset(RawTileset {
+            first_gid,
+            image: relative_to(path, &tileset.image),
+            tile_size: (tileset.tilewidth, tileset.tileheight),
+            margin: tileset.margin,
+            spacing: tileset.spacing,
+            columns: tileset.columns,
+        })
+    }
+
+    fn flatten(
+        layers: Vec<Layer>,
+        offset: Vec2,
+        properties: &HashMap<String, String>,
+        out: &mut Vec<RawLayer>,
+    ) -> Result<(), LoadError> {
+        for layer in layers {
+            let offset = offset + Vec2::new(layer.offsetx, layer.offsety);
+            let properties = inherit(properties, to_map(layer.properties));
+            let content = match layer.kind.as_str() {
+                "group" => {
+                    flatten(layer.layers, offset, &properties, out)?;
+                    continue;
+                }
+                "tilelayer" => LayerContent::Tiles(tile_data(&layer.name, layer.data)?),
+                "objectgroup" => LayerContent::Objects(
+                    layer
+                        .objects
+                        .into_iter()
+                        .map(|object| RawObject {
+                            name: object.name,
+                            class: if object.class.is_empty() {
+                                object.kind
+                            } else {
+                                object.class
+                            },
+                            position: Vec2::new(object.x, object.y),
+                            size: Vec2::new(object.width, object.height),
+                            gid: object.gid,
+                            properties: to_map(object.properties),
+                        })
+                        .collect(),
+                ),
+                // Image layers have nothing to import.
+                _ => continue,
+            };
+            out.push(RawLayer {
+                name: layer.name,
+                offset,
+                properties,
+                content,
+            });
+        }
+        Ok(())
+    }
+
+    fn tile_data(name: &str, data: Option<Value>) -> Result<Vec<u32>, LoadError> {
+        match data {
+            Some(Value::Array(gids)) => gids
+                .iter()
+                .map(|gid| {
+                    gid.as_u64()
+                        .map(|gid| gid as u32)
+                        .ok_or_else(|| parse_error(format!("layer {} has a bad tile", name)))
+                })
+                .collect(),
+            _ => Err(parse_error(format!(
+                "layer {} isn't saved as CSV, change the map's tile layer format",
+                name
+            ))),
+        }
+    }
+
+    fn to_map(properties: Vec<Property>) -> HashMap<String, String> {
+        properties
+            .into_iter()
+            .map(|property| {
+                let value = match property.value {
+                    Value::String(value) => value,
+                    value => value.to_string(),
+                };
+                (property.name, value)
+            })
+            .collect()
+    }
+}
+
+mod tmx {
+    use super::*;
+    use roxmltree::{Document, Node};
+    use std::{fs::read_to_string, str::FromStr};
+
+    fn attr<T: FromStr>(node: Node, name: &str) -> Result<T, LoadError> {
+        node.attribute(name)
+            .ok_or_else(|| {
+                parse_error(format!("<{}> has no {}", node.tag_name().name(), name))
+            })?
+            .parse()
+            .map_err(|_| parse_error(format!("<{}> has a bad {}", node.tag_name().name(), name)))
+    }
+
+    fn attr_or<T: FromStr>(node: Node, name: &str, default: T) -> Result<T, LoadError> {
+        match node.attribute(name) {
+            Some(_) => attr(node, name),
+            None => Ok(default),
+        }
+    }
+
+    fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+        node.children().find(|child| child.has_tag_name(name))
+    }
+
+    pub(super) fn load(path: &Path) -> Result<RawMap, LoadError> {
+        parse(path, &read_to_string(path)?)
+    }
+
+    // `path` is where the map was read from, which its tilesets are relative to.
+    pub(super) fn parse(path: &Path, src: &str) -> Result<RawMap, LoadError> {
+        let doc = Document::parse(src).map_err(|err| parse_error(err.to_string()))?;
+        let map = doc.root_element();
+        check_map(
+            map.attribute("orientation").unwrap_or("orthogonal"),
+            attr_or(map, "infinite", 0u8)? != 0,
+        )?;
+
+        let mut tilesets = vec![];
+        for tileset in map.children().filter(|node| node.has_tag_name("tileset")) {
+            let first_gid = attr(tileset, "firstgid")?;
+            tilesets.push(match tileset.attribute("source") {
+                Some(source) => load_external_tileset(&relative_to(path, source), first_gid)?,
+                None => raw_tileset(path, tileset, first_gid)?,
+            });
+        }
+        tilesets.sort_by_key(|tileset| tileset.first_gid);
+
+        let mut layers = vec![];
+        flatten(map, Vec2::zero(), &HashMap::new(), &mut layers)?;
+
+        Ok(RawMap {
+            width: attr(map, "width")?,
+            height: attr(map, "height")?,
+            tile_size: (attr(map, "tilewidth")?, attr(map, "tileheight")?),
+            tilesets,
+            layers,
+        })
+    }
+
+    pub(super) fn load_tileset(path: &Path, first_gid: u32) -> Result<RawTileset, LoadError> {
+        let src = read_to_string(path)?;
+        let doc = Document::parse(&src).map_err(|err| parse_error(err.to_string()))?;
+        raw_tileset(path, doc.root_element(), first_gid)
+    }
+
+    fn raw_tileset(path: &Path, tileset: Node, first_gid: u32) -> Result<RawTileset, LoadError> {
+        let image = child(tileset, "image")
+            .ok_or_else(|| parse_error("only tilesets with a single image are supported".into()))?;
+        check_tileset(RawTileset {
+            first_gid,
+            image: relative_to(path, &attr::<String>(image, "source")?),
+            tile_size: (attr(tileset, "tilewidth")?, attr(tileset, "tileheight")?),
+            margin: attr_or(tileset, "margin", 0)?,
+            spacing: attr_or(tileset, "spacing", 0)?,
+            columns: attr(tileset, "columns")?,
+        })
+    }
+
+    fn flatten(
+        parent: Node,
+        offset: Vec2,
+        properties: &HashMap<String, String>,
+        out: &mut Vec<RawLayer>,
+    ) -> Result<(), LoadError> {
+        for layer in parent.children().filter(Node::is_element) {
+            let kind = layer.tag_name().name();
+            if !["layer", "objectgroup", "group"].contains(&kind) {
+                continue;
+            }
+            let name: String = attr_or(layer, "name", String::new())?;
+            let offset = offset
+                + Vec2::new(
+                    attr_or(layer, "offsetx", 0.0)?,
+                    attr_or(layer, "offsety", 0.0)?,
+                );
+            let properties = inherit(properties, to_map(layer));
+            let content = match kind {
+                "group" => {
+                    flatten(layer, offset, &properties, out)?;
+                    continue;
+                }
+                "layer" => LayerContent::Tiles(tile_data(&name, layer)?),
+                _ => LayerContent::Objects(
+                    layer
+                        .children()
+                        .filter(|node| node.has_tag_name("object"))
+                        .map(|object| {
+                            Ok(RawObject {
+                                name: attr_or(object, "name", String::new())?,
+                                class: match object.attribute("class") {
+                                    Some(class) => class.to_string(),
+                                    None => attr_or(object, "type", String::new())?,
+                                },
+                                position: Vec2::new(attr(object, "x")?, attr(object, "y")?),
+                                size: Vec2::new(
+                                    attr_or(object, "width", 0.0)?,
+                                    attr_or(object, "height", 0.0)?,
+                                ),
+                                gid: match object.attribute("gid") {
+                                    Some(_) => Some(attr(object, "gid")?),
+                                    None => None,
+                                },
+                                properties: to_map(object),
+                            })
+                        })
+                        .collect::<Result<_, LoadError>>()?,
+                ),
+            };
+            out.push(RawLayer {
+                name,
+                offset,
+                properties,
+                content,
+            });
+        }
+        Ok(())
+    }
+
+    fn tile_data(name: &str, layer: Node) -> Result<Vec<u32>, LoadError> {
+        let data = child(layer, "data")
+            .ok_or_else(|| parse_error(format!("layer {} has no data", name)))?;
+        match data.attribute("encoding") {
+            Some("csv") => data
+                .text()
+                .unwrap_or("")
+                .split(',')
+                .map(|gid| {
+                    gid.trim()
+                        .parse()
+                        .map_err(|_| parse_error(format!("layer {} has a bad tile", name)))
+                })
+                .collect(),
+            // The old XML format, one element per tile.
+            None => data
+                .children()
+                .filter(|node| node.has_tag_name("tile"))
+                .map(|tile| attr_or(tile, "gid", 0))
+                .collect(),
+            Some(_) => Err(parse_error(format!(
+                "layer {} isn't saved as CSV, change the map's tile layer format",
+                name
+            ))),
+        }
+    }
+
+    fn to_map(node: Node) -> HashMap<String, String> {
+        let properties = match child(node, "properties") {
+            Some(properties) => properties,
+            None => return HashMap::new(),
+        };
+        properties
+            .children()
+            .filter(|node| node.has_tag_name("property"))
+            .filter_map(|property| {
+                // Multi-line strings are stored as the element's text.
+                let value = property.attribute("value").or_else(|| property.text())?;
+                Some((property.attribute("name")?.to_string(), value.to_string()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="2" height="2" tilewidth="32" tileheight="32" infinite="0">
+ <tileset firstgid="1" name="ground" tilewidth="32" tileheight="32" tilecount="4" columns="2">
+  <image source="ground.png" width="64" height="64"/>
+ </tileset>
+ <tileset firstgid="5" name="trees" tilewidth="32" tileheight="32" margin="1" spacing="2" tilecount="6" columns="3">
+  <image source="../trees.png" width="100" height="67"/>
+ </tileset>
+ <layer name="ground" width="2" height="2">
+  <properties>
+   <property name="depth" type="float" value="30"/>
+  </properties>
+  <data encoding="csv">
+1,2,
+0,4
+</data>
+ </layer>
+</map>
+"#;
+
+    const TMJ: &str = r#"{
+ "width": 2, "height": 2, "tilewidth": 32, "tileheight": 32,
+ "orientation": "orthogonal", "infinite": false,
+ "tilesets": [
+  {"firstgid": 1, "image": "ground.png", "tilewidth": 32, "tileheight": 32, "columns": 2}
+ ],
+ "layers": [
+  {"type": "group", "name": "back", "offsetx": 16, "offsety": 8,
+   "properties": [{"name": "depth", "type": "float", "value": 50}],
+   "layers": [
+    {"type": "tilelayer", "name": "hills", "data": [1, 0, 0, 2]},
+    {"type": "imagelayer", "name": "sky"}
+   ]},
+  {"type": "objectgroup", "name": "things",
+   "properties": [{"name": "depth", "type": "int", "value": 30}],
+   "objects": [
+    {"name": "tree", "type": "prop", "gid": 3, "x": 32, "y": 64, "width": 32, "height": 32,
+     "properties": [{"name": "sprite", "type": "string", "value": "beech"}]},
+    {"name": "spawn", "class": "marker", "x": 16, "y": 16}
+   ]}
+ ]
+}"#;
+
+    fn tmx(src: &str) -> Result<TiledMap, LoadError> {
+        let path = Path::new("maps/level.tmx");
+        TiledMap::build(path, tmx::parse(path, src)?)
+    }
+
+    fn tmj(src: &str) -> Result<TiledMap, LoadError> {
+        let path = Path::new("maps/level.tmj");
+        TiledMap::build(path, tmj::parse(path, src)?)
+    }
+
+    // `TMX` with its ground layer swapped for `layers`.
+    fn tmx_with_layers(layers: &str) -> String {
+        let start = TMX.find(" <layer").expect("fixture has a layer");
+        let end = TMX.find("</map>").expect("fixture is closed");
+        format!("{}{}{}", &TMX[..start], layers, &TMX[end..])
+    }
+
+    fn tiles(layer: &TiledLayer) -> Vec<Option<(u32, bool, bool)>> {
+        let grid = layer.asset.tiles.as_ref().expect("tile layers have a grid");
+        grid.tiles
+            .iter()
+            .map(|tile| tile.map(|tile| (tile.index, tile.flip_x, tile.flip_y)))
+            .collect()
+    }
+
+    fn error_message(result: Result<TiledMap, LoadError>) -> String {
+        match result {
+            Err(LoadError::Parse(msg)) => msg,
+            Err(err) => panic!("expected a parse error, got {}", err),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn tile_layer_is_placed_at_its_depth() {
+        let map = tmx(TMX).expect("map loads");
+        assert_eq!(map.layers.len(), 1);
+        let layer = &map.layers[0];
+        assert_eq!(layer.name, "ground");
+        assert_eq!(layer.asset.id, "maps/level.tmx#0:1");
+        assert_eq!(layer.asset.frames, vec![PathBuf::from("maps/ground.png")]);
+        // The map is 64 pixels, two metres, square.
+        assert_eq!(layer.position, Vec3::new(1.0, -1.0, 30.0));
+        assert_eq!(
+            tiles(layer),
+            vec![
+                Some((0, false, false)),
+                Some((1, false, false)),
+                None,
+                Some((3, false, false))
+            ]
+        );
+        assert!(map.objects.is_empty());
+    }
+
+    #[test]
+    fn layer_is_split_by_tileset() {
+        let map = tmx(&TMX.replace("1,2,\n0,4", "1,5,\n6,0")).expect("map loads");
+        assert_eq!(map.layers.len(), 2);
+        let (ground, trees) = (&map.layers[0], &map.layers[1]);
+        assert_eq!(ground.asset.id, "maps/level.tmx#0:1");
+        assert_eq!(
+            tiles(ground),
+            vec![Some((0, false, false)), None, None, None]
+        );
+        assert_eq!(trees.asset.id, "maps/level.tmx#0:5");
+        assert_eq!(trees.asset.frames, vec![PathBuf::from("maps/../trees.png")]);
+        assert_eq!(
+            tiles(trees),
+            vec![None, Some((0, false, false)), Some((1, false, false)), None]
+        );
+        let grid = trees.asset.tiles.as_ref().expect("tile layers have a grid");
+        assert_eq!((grid.margin, grid.spacing, grid.columns), (1, 2, 3));
+        assert_eq!(ground.position, trees.position);
+    }
+
+    #[test]
+    fn flip_bits_are_read_and_rotation_flags_ignored() {
+        let data = format!(
+            "{},{},{},{}",
+            FLIP_X | 1,
+            FLIP_Y | 2,
+            FLIP_X | FLIP_Y | FLIP_DIAGONAL | 3,
+            ROTATE_HEXAGONAL | 4
+        );
+        let map = tmx(&TMX.replace("1,2,\n0,4", &data)).expect("map loads");
+        assert_eq!(
+            tiles(&map.layers[0]),
+            vec![
+                Some((0, true, false)),
+                Some((1, false, true)),
+                Some((2, true, true)),
+                Some((3, false, false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn xml_tile_data_is_read() {
+        let map = tmx(&TMX.replace(
+            "<data encoding=\"csv\">\n1,2,\n0,4\n</data>",
+            "<data><tile gid=\"1\"/><tile gid=\"2\"/><tile/><tile gid=\"4\"/></data>",
+        ))
+        .expect("map loads");
+        assert_eq!(
+            tiles(&map.layers[0]),
+            vec![
+                Some((0, false, false)),
+                Some((1, false, false)),
+                None,
+                Some((3, false, false))
+            ]
+        );
+    }
+
+    #[test]
+    fn groups_pass_offsets_and_properties_down() {
+        let map = tmx(&tmx_with_layers(
+            r#" <group name="back" offsetx="16" offsety="8">
+  <properties>
+   <property name="depth" type="float" value="50"/>
+  </properties>
+  <layer name="hills" width="2" height="2">
+   <data encoding="csv">1,0,0,2</data>
+  </layer>
+  <layer name="near" width="2" height="2" offsetx="-16">
+   <properties>
+    <property name="depth" type="float" value="40.5"/>
+   </properties>
+   <data encoding="csv">0,1,0,0</data>
+  </layer>
+ </group>
+"#,
+        ))
+        .expect("map loads");
+        assert_eq!(map.layers.len(), 2);
+        assert_eq!(map.layers[0].name, "hills");
+        assert_eq!(map.layers[0].position, Vec3::new(1.5, -1.25, 50.0));
+        assert_eq!(map.layers[1].name, "near");
+        assert_eq!(map.layers[1].position, Vec3::new(1.0, -1.25, 40.5));
+    }
+
+    #[test]
+    fn tile_objects_hang_from_their_bottom_left() {
+        let map = tmx(&tmx_with_layers(
+            r#" <objectgroup name="things" offsetx="32">
+  <properties>
+   <property name="depth" value="30"/>
+  </properties>
+  <object id="1" name="tree" type="prop" gid="3" x="32" y="64" width="32" height="32">
+   <properties>
+    <property name="sprite" value="beech"/>
+   </properties>
+  </object>
+  <object id="2" name="pond" class="water" x="0" y="0" width="64" height="32"/>
+  <object id="3" name="spawn" x="16" y="16"><point/></object>
+ </objectgroup>
+"#,
+        ))
+        .expect("map loads");
+        assert!(map.layers.is_empty());
+        assert_eq!(map.objects.len(), 3);
+
+        let tree = &map.objects[0];
+        assert_eq!((tree.name.as_str(), tree.class.as_str()), ("tree", "prop"));
+        assert_eq!(tree.position, Vec3::new(2.5, -1.5, 30.0));
+        assert_eq!(tree.size, Vec2::new(1.0, 1.0));
+        assert_eq!(
+            tree.properties.get("sprite").map(String::as_str),
+            Some("beech")
+        );
+
+        let pond = &map.objects[1];
+        assert_eq!(pond.class, "water");
+        assert_eq!(pond.position, Vec3::new(2.0, -0.5, 30.0));
+
+        let spawn = &map.objects[2];
+        assert_eq!(spawn.position, Vec3::new(1.5, -0.5, 30.0));
+        assert_eq!(spawn.size, Vec2::zero());
+    }
+
+    #[test]
+    fn json_maps_load_like_xml_ones() {
+        let map = tmj(TMJ).expect("map loads");
+        assert_eq!(map.layers.len(), 1);
+        let hills = &map.layers[0];
+        assert_eq!(hills.asset.id, "maps/level.tmj#0:1");
+        assert_eq!(hills.position, Vec3::new(1.5, -1.25, 50.0));
+        assert_eq!(
+            tiles(hills),
+            vec![Some((0, false, false)), None, None, Some((1, false, false))]
+        );
+
+        assert_eq!(map.objects.len(), 2);
+        let tree = &map.objects[0];
+        assert_eq!(tree.class, "prop");
+        assert_eq!(tree.position, Vec3::new(1.5, -1.5, 30.0));
+        assert_eq!(
+            tree.properties.get("sprite").map(String::as_str),
+            Some("beech")
+        );
+        let spawn = &map.objects[1];
+        assert_eq!(spawn.class, "marker");
+        assert_eq!(spawn.position, Vec3::new(0.5, -0.5, 30.0));
+    }
+
+    #[test]
+    fn xml_errors() {
+        let cases = [
+            (
+                TMX.replace("orientation=\"orthogonal\"", "orientation=\"isometric\""),
+                "isometric maps aren't supported",
+            ),
+            (
+                TMX.replace("infinite=\"0\"", "infinite=\"1\""),
+                "infinite maps aren't supported",
+            ),
+            ("<map".to_string(), ""),
+            (
+                TMX.replace(" width=\"2\" height", " height"),
+                "<map> has no width",
+            ),
+            (
+                TMX.replace("width=\"2\" height", "width=\"two\" height"),
+                "<map> has a bad width",
+            ),
+            (
+                TMX.replace(
+                    "  <image source=\"ground.png\" width=\"64\" height=\"64\"/>\n",
+                    "",
+                ),
+                "only tilesets with a single image are supported",
+            ),
+            (
+                TMX.replace("columns=\"2\"", "columns=\"0\""),
+                "has no columns",
+            ),
+            (
+                TMX.replace(
+                    "tilewidth=\"32\" tileheight=\"32\" margin",
+                    "tilewidth=\"16\" tileheight=\"16\" margin",
+                )
+                .replace("1,2,\n0,4", "5,0,0,0"),
+                "doesn't use the map's tile size",
+            ),
+            (
+                TMX.replace(
+                    "   <property name=\"depth\" type=\"float\" value=\"30\"/>\n",
+                    "",
+                ),
+                "layer ground needs a depth property",
+            ),
+            (
+                TMX.replace("value=\"30\"", "value=\"far\""),
+                "layer ground has a non numeric depth",
+            ),
+            (
+                TMX.replace("1,2,\n0,4", "1,2,0"),
+                "layer ground has 3 tiles, expected 4",
+            ),
+            (
+                TMX.replace("1,2,\n0,4", "1,2,x,4"),
+                "layer ground has a bad tile",
+            ),
+            (
+                TMX.replace("encoding=\"csv\"", "encoding=\"base64\""),
+                "layer ground isn't saved as CSV",
+            ),
+            (
+                TMX.replace("<data encoding=\"csv\">\n1,2,\n0,4\n</data>", ""),
+                "layer ground has no data",
+            ),
+            (
+                TMX.replace("firstgid=\"1\"", "firstgid=\"2\""),
+                "tile 1 has no tileset",
+            ),
+            (
+                TMX.replace(
+                    "<tileset firstgid=\"5\" name",
+                    "<tileset firstgid=\"5\" source=\"trees.png\" name",
+                ),
+                "is not a Tiled tileset",
+            ),
+        ];
+        for (src, expected) in cases.iter() {
+            let msg = error_message(tmx(src));
+            assert!(
+                msg.contains(expected),
+                "{:?} doesn't mention {:?}",
+                msg,
+                expected
+            );
+        }
+
+        match tmx(&TMX.replace(
+            "<tileset firstgid=\"5\" name",
+            "<tileset firstgid=\"5\" source=\"missing.tsx\" name",
+        )) {
+            Err(LoadError::Io(_)) => (),
+            _ => panic!("a missing tileset file is an io error"),
+        }
+    }
+
+    #[test]
+    fn json_errors() {
+        let cases = [
+            ("{".to_string(), ""),
+            (
+                TMJ.replace("\"firstgid\": 1, ", ""),
+                "tileset has no firstgid",
+            ),
+            (
+                TMJ.replace("\"columns\": 2", "\"columns\": 0"),
+                "has no columns",
+            ),
+            (
+                TMJ.replace("[1, 0, 0, 2]", "\"AQAAAA==\""),
+                "layer hills isn't saved as CSV",
+            ),
+            (
+                TMJ.replace("[1, 0, 0, 2]", "[1, 0, -1, 2]"),
+                "layer hills has a bad tile",
+            ),
+            (
+                TMJ.replace("[1, 0, 0, 2]", "[1, 0, 0]"),
+                "layer hills has 3 tiles, expected 4",
+            ),
+            (
+                TMJ.replace("\"value\": 50", "\"value\": \"far\""),
+                "layer hills has a non numeric depth",
+            ),
+            (
+                TMJ.replace("\"image\": \"ground.png\"", "\"source\": \"ground.png\""),
+                "is not a Tiled tileset",
+            ),
+        ];
+        for (src, expected) in cases.iter() {
+            let msg = error_message(tmj(src));
+            assert!(
+                msg.contains(expected),
+                "{:?} doesn't mention {:?}",
+                msg,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn only_tiled_files_load() {
+        let msg = error_message(TiledMap::load("maps/level.png"));
+        assert!(msg.contains("is not a Tiled map"));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    camera::sky::{Sky, SkyGradient},
+    gpu_primitives::CameraUniform,
+};
+use hecs::World;
+use std::time::{Duration, Instant};
+
+const HOURS_PER_DAY: f32 = 24.0;
+
+/// A clock for the whole scene, blending its lighting between keyframes as the day goes on.
+/// Spawn one; while it has keyframes it lights every sprite with their ambient colour, paints
+/// the gradient of every camera's [`Sky`] and colours the fog of every camera's
+/// [`Atmosphere`](crate::camera::atmosphere::Atmosphere).
+#[derive(Clone, Debug)]
+pub struct TimeOfDay {
+    /// Hours since midnight, from 0 to 24.
+    pub hour: f32,
+    /// In-game hours that pass every real second, zero stops the clock.
+    pub hours_per_second: f32,
+    /// Sorted by hour. The last blends into the first across midnight.
+    pub keyframes: Vec<(f32, Lighting)>,
+}
+
+/// The lighting at an hour of the day.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lighting {
+    /// Linear RGB multiplied with every sprite.
+    pub ambient: [f32; 3],
+    pub sky: SkyGradient,
+    /// Linear RGB of the fog. How thick it is stays up to each camera.
+    pub fog: [f32; 3],
+}
+
+impl TimeOfDay {
+    pub fn new(hour: f32) -> Self {
+        TimeOfDay {
+            hour: hour.rem_euclid(HOURS_PER_DAY),
+            hours_per_second: 0.0,
+            keyframes: vec![],
+        }
+    }
+    pub fn with_speed(mut self, hours_per_second: f32) -> Self {
+        self.hours_per_second = hours_per_second;
+        self
+    }
+    pub fn with_keyframe(mut self, hour: f32, lighting: Lighting) -> Self {
+        let hour = hour.rem_euclid(HOURS_PER_DAY);
+        let i = self
+            .keyframes
+            .iter()
+            .take_while(|(h, _)| *h <= hour)
+            .count();
+        self.keyframes.insert(i, (hour, lighting));
+        self
+    }
+    /// The lighting now, `None` without keyframes.
+    pub fn lighting(&self) -> Option<Lighting> {
+        let last = self.keyframes.last()?;
+        let first = self.keyframes.first()?;
+        // The keyframes either side of the hour, wrapping around midnight.
+        let next = self.keyframes.iter().position(|(h, _)| *h > self.hour);
+        let ((from_hour, from), (to_hour, to)) = match next {
+            Some(0) | None => (*last, *first),
+            Some(i) => (self.keyframes[i - 1], self.keyframes[i]),
+        };
+
+        let span = (to_hour - from_hour).rem_euclid(HOURS_PER_DAY);
+        if span <= 0.0 {
+            return Some(from);
+        }
+        let t = (self.hour - from_hour).rem_euclid(HOURS_PER_DAY) / span;
+        Some(from.lerp(&to, t))
+    }
+}
+
+impl Lighting {
+    pub fn new(ambient: [f32; 3], sky: SkyGradient, fog: [f32; 3]) -> Self {
+        Lighting { ambient, sky, fog }
+    }
+    pub fn lerp(&self, other: &Lighting, t: f32) -> Lighting {
+        Lighting {
+            ambient: lerp3(self.ambient, other.ambient, t),
+            sky: SkyGradient {
+                top: lerp4(self.sky.top, other.sky.top, t),
+                bottom: lerp4(self.sky.bottom, other.sky.bottom, t),
+            },
+            fog: lerp3(self.fog, other.fog, t),
+        }
+    }
+    pub(crate) fn apply(&self, uniform: &mut CameraUniform) {
+        let [r, g, b] = self.ambient;
+        uniform.ambient = [r, g, b, 1.0];
+        let [r, g, b] = self.fog;
+        uniform.fog_colour = [r, g, b, uniform.fog_colour[3]];
+    }
+    pub(crate) fn apply_sky(&self, sky: &mut Sky) {
+        sky.gradient = Some(self.sky);
+    }
+}
+
+fn lerp3(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    let [x, y, z, _] = lerp4([a[0], a[1], a[2], 0.0], [b[0], b[1], b[2], 0.0], t);
+    [x, y, z]
+}
+
+fn lerp4(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let mut out = a;
+    for (i, out) in out.iter_mut().enumerate() {
+        *out = a[i] + (b[i] - a[i]) * t;
+    }
+    out
+}
+
+/// Moves every [`TimeOfDay`] on by the time since the last frame.
+pub fn advance_time_of_day(world: &World, dt: Duration, _instant: Instant) {
+    for (_, time) in world.query::<&mut TimeOfDay>().iter() {
+        time.hour =
+            (time.hour + time.hours_per_second * dt.as_secs_f32()).rem_euclid(HOURS_PER_DAY);
+    }
+}
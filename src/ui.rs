@@ -0,0 +1,145 @@
+use crate::{text::WHITE, Rect};
+use glam::Vec2;
+use hecs::{Entity, World};
+
+/// The point of the screen a [`UiNode`] is placed from, which is also the point of the node
+/// that sits there, so a `BottomRight` node with no offset fills the bottom right corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Centre,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// How far across and down the screen the anchor is, from 0 to 1.
+    pub fn fraction(self) -> Vec2 {
+        let x = match self {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => 0.0,
+            Anchor::Top | Anchor::Centre | Anchor::Bottom => 0.5,
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => 1.0,
+        };
+        let y = match self {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => 0.0,
+            Anchor::Left | Anchor::Centre | Anchor::Right => 0.5,
+            Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => 1.0,
+        };
+        Vec2::new(x, y)
+    }
+}
+
+/// A rectangle of the screen overlay, in pixels with y down, drawn after every camera and
+/// unaffected by them. Give it a [`UiImage`] or a [`crate::text::Text`] to draw, and a
+/// [`UiInteraction`] to hit test it against the mouse.
+#[derive(Clone, Copy, Debug)]
+pub struct UiNode {
+    pub anchor: Anchor,
+    /// From the anchor, in pixels.
+    pub offset: Vec2,
+    pub size: Vec2,
+    /// Nodes on higher layers draw over and take the mouse from lower ones.
+    pub layer: i32,
+}
+
+impl UiNode {
+    pub fn new(anchor: Anchor, offset: Vec2, size: Vec2) -> Self {
+        UiNode {
+            anchor,
+            offset,
+            size,
+            layer: 0,
+        }
+    }
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+    /// Where the node is on a screen `screen` pixels big.
+    pub fn rect(&self, screen: Vec2) -> Rect {
+        let fraction = self.anchor.fraction();
+        let min = screen * fraction + self.offset - self.size * fraction;
+        Rect::new(min, min + self.size)
+    }
+}
+
+/// Draws a frame of a sprite over a [`UiNode`], stretched to fit. The borders of nine-slice
+/// sprites keep their size.
+#[derive(Clone, Debug)]
+pub struct UiImage {
+    pub sprite: String,
+    pub frame_id: u32,
+    /// Linear RGBA, multiplied with the image.
+    pub colour: [f32; 4],
+    /// Screen pixels per image pixel for nine-slice borders.
+    pub border_scale: f32,
+}
+
+impl UiImage {
+    pub fn new(sprite: &str) -> Self {
+        UiImage {
+            sprite: sprite.to_string(),
+            frame_id: 0,
+            colour: WHITE,
+            border_scale: 1.0,
+        }
+    }
+    pub fn with_colour(mut self, colour: [f32; 4]) -> Self {
+        self.colour = colour;
+        self
+    }
+    pub fn with_border_scale(mut self, border_scale: f32) -> Self {
+        self.border_scale = border_scale;
+        self
+    }
+}
+
+/// The mouse's state against a [`UiNode`], updated at the start of each frame. Only the topmost
+/// node under the mouse is hovered.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UiInteraction {
+    pub hovered: bool,
+    /// The left button went down on the node and hasn't been released.
+    pub pressed: bool,
+    /// The left button was released over the node this frame after going down on it.
+    pub clicked: bool,
+}
+
+/// The mouse, in window pixels from the top left. One is spawned with the game.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mouse {
+    pub position: Vec2,
+    pub left_down: bool,
+    /// The left button went down or up since the last frame.
+    pub left_pressed: bool,
+    pub left_released: bool,
+}
+
+/// Hit tests the interactive nodes against the mouse on a screen `screen` pixels big.
+pub(crate) fn update_interactions(world: &World, mouse: &Mouse, screen: Vec2) {
+    let mut top: Option<(i32, Entity)> = None;
+    for (entity, (node, _)) in world.query::<(&UiNode, &UiInteraction)>().iter() {
+        let over = node.rect(screen).contains(mouse.position);
+        if over && top.map_or(true, |(layer, _)| node.layer >= layer) {
+            top = Some((node.layer, entity));
+        }
+    }
+
+    for (entity, interaction) in world.query::<&mut UiInteraction>().iter() {
+        let hovered = top.map_or(false, |(_, top)| top == entity);
+        interaction.hovered = hovered;
+        interaction.clicked = false;
+        if hovered && mouse.left_pressed {
+            interaction.pressed = true;
+        }
+        if mouse.left_released {
+            interaction.clicked = interaction.pressed && hovered;
+            interaction.pressed = false;
+        }
+    }
+}